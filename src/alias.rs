@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use thiserror::Error;
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::operand::alias::RegisterAlias;
+use crate::architecture::batpu2::operand::definition::{Definition, DefinitionKind};
+use crate::architecture::batpu2::operand::Operand;
+use crate::architecture::batpu2::operand::pair::RegisterPair;
+use crate::architecture::batpu2::operand::register::Register;
+use crate::resolve::is_global_label;
+use crate::source::SourceLocation;
+
+/// The register of each alias, and where the alias was defined
+type Aliases = HashMap<String, (Register, SourceLocation)>;
+type Pairs = HashMap<String, (Register, Register)>;
+
+#[derive(Debug, Error)]
+pub enum AliasError {
+    #[error("{location}: Unknown alias: {name}")]
+    UnknownAlias {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Alias {name} is already defined")]
+    DuplicateAlias {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Unknown register pair: {name}")]
+    UnknownPair {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Register pair {name} is already defined")]
+    DuplicatePair {
+        location: SourceLocation,
+        name: String
+    },
+}
+
+/// Binds every alias usage to its register. Aliases are live from their definition to the end
+/// of their file, local aliases only until the next global label. Register pairs work like
+/// global aliases
+pub fn resolve_aliases(program: &mut [Instruction]) -> Result<(), AliasError> {
+    info!("Resolving register aliases...");
+    let mut global: Aliases = HashMap::new();
+    let mut local: Aliases = HashMap::new();
+    let mut pairs: Pairs = HashMap::new();
+    let mut file = String::new();
+
+    for instruction in program.iter_mut() {
+        if instruction.source.file != file {
+            file = instruction.source.file.clone();
+            global.clear();
+            local.clear();
+            pairs.clear();
+        }
+
+        match instruction.opcode {
+            Opcode::_Label => {
+                if let Operand::Name(name) = &instruction.operands[0] {
+                    if is_global_label(name) {
+                        local.clear();
+                    }
+                }
+            },
+            Opcode::_Alias => define_alias(instruction, &mut global, &mut local)?,
+            Opcode::_Definition => define_register(instruction, &mut global)?,
+            Opcode::_Pair => define_pair(instruction, &mut pairs)?,
+            _ => bind_aliases(instruction, &global, &local, &pairs)?
+        }
+    }
+    trace!("{} global aliases found: {:?}", global.len(), global.keys());
+    Ok(())
+}
+
+fn define_alias(instruction: &Instruction, global: &mut Aliases, local: &mut Aliases) -> Result<(), AliasError> {
+    if let Operand::Alias(alias) = &instruction.operands[0] {
+        let register = match alias.register {
+            Some(r) => r,
+            // This should never happen, as the parser should catch this
+            None => {
+                return Err(AliasError::UnknownAlias {
+                    location: instruction.source.clone(),
+                    name: alias.name.clone()
+                });
+            }
+        };
+
+        let scope = if alias.local { &*local } else { &*global };
+        if scope.contains_key(&alias.name) {
+            return Err(AliasError::DuplicateAlias {
+                location: instruction.source.clone(),
+                name: alias.name.clone()
+            });
+        }
+
+        for (name, (r, defined)) in local.iter().chain(global.iter()) {
+            if *r == register && *name != alias.name {
+                warn!("{}: Aliases {} and {} both refer to {}, {} is defined at {}",
+                    instruction.source, alias.name, name, register, name, defined);
+            }
+        }
+
+        trace!("Found alias: {} = {}", alias.name, register);
+        let entry = (register, instruction.source.clone());
+        if alias.local {
+            local.insert(alias.name.clone(), entry);
+        } else {
+            global.insert(alias.name.clone(), entry);
+        }
+    }
+    Ok(())
+}
+
+/// `define NAME:reg rN` names a register just like `alias NAME rN` does
+fn define_register(instruction: &Instruction, global: &mut Aliases) -> Result<(), AliasError> {
+    if let Operand::Def(Definition { name, value: Some(value), kind: Some(DefinitionKind::Register) }) = &instruction.operands[0] {
+        if global.contains_key(name) {
+            return Err(AliasError::DuplicateAlias {
+                location: instruction.source.clone(),
+                name: name.clone()
+            });
+        }
+
+        // The parser only accepts registers for these, so the value is always 0 to 15
+        if let Ok(register) = Register::from_str(&value.to_string()) {
+            trace!("Found register definition: {} = {}", name, register);
+            global.insert(name.clone(), (register, instruction.source.clone()));
+        }
+    }
+    Ok(())
+}
+
+fn define_pair(instruction: &Instruction, pairs: &mut Pairs) -> Result<(), AliasError> {
+    if let Operand::Pair(RegisterPair { name, registers: Some(registers) }) = &instruction.operands[0] {
+        if pairs.contains_key(name) {
+            return Err(AliasError::DuplicatePair {
+                location: instruction.source.clone(),
+                name: name.clone()
+            });
+        }
+
+        trace!("Found register pair: {} = {}:{}", name, registers.0, registers.1);
+        pairs.insert(name.clone(), *registers);
+    }
+    Ok(())
+}
+
+fn bind_aliases(instruction: &mut Instruction, global: &Aliases, local: &Aliases, pairs: &Pairs) -> Result<(), AliasError> {
+    let opcode = instruction.opcode;
+    for (operand, location) in instruction.located_operands_mut() {
+        // The second operand of MUL, DIV and MOD, and the right side of the comparisons of .if and
+        // .while, parse as a definition when they are a name
+        if let (Opcode::MUL | Opcode::DIV | Opcode::MOD | Opcode::_If | Opcode::_While, Operand::Def(def)) = (opcode, &*operand) {
+            let register = local.get(&def.name).or_else(|| global.get(&def.name));
+            if let (Some((r, _)), Some(mut alias)) = (register, RegisterAlias::new_opr(&def.name)) {
+                trace!("Replacing {} with {}", def.name, r);
+                alias.register = Some(*r);
+                *operand = Operand::Alias(alias);
+                continue;
+            }
+        }
+        if let Operand::Instr(inner) = operand {
+            bind_aliases(inner, global, local, pairs)?;
+            continue;
+        }
+        if let Operand::Pair(pair) = operand {
+            if pair.registers.is_none() {
+                match pairs.get(&pair.name) {
+                    Some(registers) => pair.registers = Some(*registers),
+                    None => {
+                        return Err(AliasError::UnknownPair {
+                            location: location.clone(),
+                            name: pair.name.clone()
+                        });
+                    }
+                }
+            }
+            continue;
+        }
+        // Virtual registers get their register from the register allocator
+        if let Operand::Alias(alias) = operand {
+            if alias.is_virtual() {
+                continue;
+            }
+            match local.get(&alias.name).or_else(|| global.get(&alias.name)) {
+                Some((r, _)) => {
+                    trace!("Replacing {} with {}", alias.name, r);
+                    alias.register = Some(*r);
+                },
+                None => {
+                    return Err(AliasError::UnknownAlias {
+                        location: location.clone(),
+                        name: alias.name.clone()
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
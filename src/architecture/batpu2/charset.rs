@@ -0,0 +1,35 @@
+/// Characters of the BatPU-2 character display, in the order of their codes
+pub const DISPLAY_CHARACTERS: [char; 30] = [
+    ' ', 'a', 'b', 'c', 'd', 'e', 'f', 'g',
+    'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w',
+    'x', 'y', 'z', '.', '!', '?'
+];
+
+/// Number of characters the display can show at once
+pub const DISPLAY_LENGTH: usize = 10;
+
+/// How character literals are turned into immediates
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Charset {
+    /// Codes of the character display, letters are case-insensitive
+    #[default]
+    Display,
+    /// Plain ASCII codes, for programs that do their own character handling
+    Ascii
+}
+
+impl Charset {
+    /// Returns None if the character has no code in this charset
+    pub fn encode(&self, c: char) -> Option<u8> {
+        match self {
+            Charset::Display => {
+                let lower = c.to_ascii_lowercase();
+                DISPLAY_CHARACTERS.iter().position(|d| *d == lower).map(|i| i as u8)
+            },
+            Charset::Ascii => {
+                if c.is_ascii() { Some(c as u8) } else { None }
+            }
+        }
+    }
+}
@@ -1,19 +1,29 @@
 use std::fmt::Display;
 use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::operand::alias::RegisterAlias;
 use crate::architecture::batpu2::operand::condition::Condition;
 use crate::architecture::batpu2::operand::definition::Definition;
+use crate::architecture::batpu2::operand::expression::Expression;
 use crate::architecture::batpu2::operand::immediate::{Address, Immediate, Offset};
 use crate::architecture::batpu2::operand::label::Label;
 use crate::architecture::batpu2::operand::Operand;
 use crate::architecture::batpu2::operand::port::Port;
 use crate::architecture::batpu2::operand::register::Register;
+use crate::source::SourceLocation;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Instruction {
     pub opcode: Opcode,
     pub(crate) operands: Vec<Operand>,
     pub location: Option<Address>,
-    pub(crate) encoding: Option<u16>
+    pub(crate) encoding: Option<u16>,
+    pub source: SourceLocation,
+    /// Where each operand starts, empty for generated instructions
+    pub(crate) operand_sources: Vec<SourceLocation>,
+    /// Input left when the parser reached each operand, see `parser::parse`
+    pub(crate) operand_offsets: Vec<usize>,
+    /// The branch mnemonic a `BRH` was written as, like `bne`, which only the listing shows
+    pub(crate) mnemonic: Option<String>
 }
 
 /// Types of instructions:
@@ -21,25 +31,76 @@ pub struct Instruction {
 /// {CMP|LSH|RSH|MOV} {reg}, {reg}
 /// {LOD|STR} {reg}, {reg}, {def|offset}?
 /// {LDI|ADI} {reg}, {imm|port|def|char}
-/// {BRH} {cond}, {label|addr}
+/// {BRH} {cond}, {label|addr|expr}
+/// {BEQ|BNE|BGE|BLT} {label|addr|expr}, which is BRH with the condition of the mnemonic
+/// {SUBI|ANDI|XORI} {reg}, {imm|port|def|char}, {reg}?
+/// {CMPI} {reg}, {imm|port|def|char}
+/// {PUSH|POP} {reg}
+/// {PUSHALL|POPALL} ({reg}(, {reg})*)?
+/// {LDI16} {pair}, {expr}
+/// {ADD16|SUB16} {pair}, {pair}, {pair}?
+/// {CMP16} {pair}, {pair}
+/// {INC16} {pair}
+/// {MUL|DIV|MOD} {reg}, {reg|imm|def|char}, {reg}?
 /// {INC|DEC} {reg}
-/// {JMP} {label|addr}
+/// {JMP|CAL} {label|addr|expr}
 /// {NOP|HLT|RET}
+/// {alias} ({reg}(, {reg})*)?, an instruction alias from the project's config
+///
+/// A {reg} can also be an alias, or a virtual register like %count that gets its register later
 ///
 /// special cases:
 /// .{identifier}
-/// define {identifier} {value}
-
+/// define {identifier}(:{imm|offset|port|addr|reg})? {value}
+/// alias (local)? {identifier} {reg}
+/// .assert {expr}, "{message}"?
+/// {.error|.warning|.print} "{message}"
+/// .include "std"
+/// {.print_reg_number|.scratch|.port_base} {reg}
+/// {.global|.extern} {identifier}(, {identifier})*
+/// .var {identifier}
+/// .array {identifier} {length}
+/// .struct {identifier}, .field {identifier} {length}?, .endstruct
+/// .table {identifier} {imm|char|expr|"{text}"}(, {imm|char|expr|"{text}"})*
+/// .init_tables
+/// .org {addr}
+/// .align {bytes}
+/// .fill {count}, {instruction}
+/// .filler {instruction}
+/// .stack {reg}, {length}
+/// .pair {identifier} {reg}, {reg}
+/// .sprite "{file}"(, {reg|imm}, {reg|imm})?
+/// {.if|.while} ({reg} {comparison} {reg|imm|def|char} | {cond}), .else, .endif, .endwhile
+/// .loop {reg}, .endloop
 impl Instruction {
     pub fn new(opcode: Opcode) -> Instruction {
         Instruction {
             opcode,
             operands: Vec::new(),
             location: None,
-            encoding: None
+            encoding: None,
+            source: SourceLocation::default(),
+            operand_sources: Vec::new(),
+            operand_offsets: Vec::new(),
+            mnemonic: None
         }
     }
 
+    /// Where the operand at `index` starts, or the instruction itself if that is not known
+    pub fn operand_source(&self, index: usize) -> &SourceLocation {
+        self.operand_sources.get(index).unwrap_or(&self.source)
+    }
+
+    /// Every operand along with where it starts, see `operand_source`
+    pub fn located_operands_mut(&mut self) -> impl Iterator<Item = (&mut Operand, &SourceLocation)> {
+        let source = &self.source;
+        let sources = &self.operand_sources;
+        self.operands
+            .iter_mut()
+            .enumerate()
+            .map(move |(i, operand)| (operand, sources.get(i).unwrap_or(source)))
+    }
+
     pub fn add_operand(&mut self, operand: Operand) {
         self.operands.push(operand);
     }
@@ -83,23 +144,29 @@ impl Instruction {
     pub fn add_offset(&mut self, i4: Offset) {
         self.add_operand(Operand::Offset(i4));
     }
+
+    pub fn add_expression(&mut self, expr: Expression) {
+        self.add_operand(Operand::Expr(expr));
+    }
+
+    pub fn add_alias(&mut self, alias: RegisterAlias) {
+        self.add_operand(Operand::Alias(alias));
+    }
+
+    pub fn add_text(&mut self, text: String) {
+        self.add_operand(Operand::Text(text));
+    }
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut text: String = String::new();
 
-        if self.opcode == Opcode::_Definition {
-            text.push_str("define");
-        } else if self.opcode == Opcode::_Label {
-            text.push_str(".");
-        } else {
-            text.push_str(&format!("{}", self.opcode));
-        }
+        text.push_str(&format!("{}", self.opcode));
 
         for op in &self.operands {
-            let op_text = if let Operand::Name(name) = op {
-                format!("{}", *name)
+            let op_text = if let (Opcode::_Label, Operand::Name(name)) = (self.opcode, op) {
+                (*name).to_string()
             } else {
                 format!(" {}", *op)
             };
@@ -1,30 +1,52 @@
+pub mod charset;
 pub mod operand;
 pub mod opcode;
 pub mod instruction;
 
-pub const KEYWORDS: [&str; 76] = [
-    "define",
-    // Opcodes (1-23)
+use std::sync::LazyLock;
+
+/// Words that start a definition or an alias
+pub const DIRECTIVES: [&str; 3] = ["define", "alias", "local"];
+
+pub const OPCODES: [&str; 51] = [
     "nop", "hlt", "add", "sub",
     "nor", "and", "xor", "rsh",
     "ldi", "adi", "jmp", "brh",
     "cal", "ret", "lod", "str",
     "cmp", "mov", "lsh", "inc",
     "dec", "not", "neg",
-    // Registers (24-39)
+    "beq", "bne", "bge", "blt",
+    "bzs", "bzc", "bcs", "bcc",
+    "bz", "bnz", "bc", "bnc",
+    "cmpi", "subi", "andi", "xori",
+    "push", "pop", "pushall", "popall",
+    "ldi16", "add16", "sub16", "inc16", "cmp16",
+    "mul", "div", "mod"
+];
+
+pub const REGISTERS: [&str; 16] = [
     "r0", "r1", "r2", "r3",
     "r4", "r5", "r6", "r7",
     "r8", "r9", "r10", "r11",
-    "r12", "r13", "r14", "r15",
-    // Conditions (40-55)
+    "r12", "r13", "r14", "r15"
+];
+
+pub const CONDITIONS: [&str; 20] = [
     "zs", "zc", "cs", "cc",
     "lt", "ge", "eq", "ne",
     "=", "!=", ">=", "<",
     "nc", "c", "z", "nz",
-    "notcarry", "carry", "zero", "notzero",
-    // Ports (56-75)
+    "notcarry", "carry", "zero", "notzero"
+];
+
+pub const PORTS: [&str; 16] = [
     "pixel_x", "pixel_y", "draw_pixel", "clear_pixel",
     "load_pixel", "buffer_screen", "clear_screen_buffer", "write_char", "buffer_chars",
     "clear_chars_buffer", "show_number", "clear_number", "signed_mode", "unsigned_mode",
     "rng", "controller_input"
-];
\ No newline at end of file
+];
+
+/// Every reserved word, none of them can be used as a name
+pub static KEYWORDS: LazyLock<Vec<&str>> = LazyLock::new(|| {
+    [&DIRECTIVES[..], &OPCODES, &REGISTERS, &CONDITIONS, &PORTS].concat()
+});
@@ -26,9 +26,207 @@ pub enum Opcode {
     DEC,
     NOT,
     NEG,
-    // BNE, BEQ, BGE, BLT, possible extra pseudo-ops???
+    CMPI,
+    SUBI,
+    ANDI,
+    XORI,
+    PUSH,
+    POP,
+    PUSHALL,
+    POPALL,
+    LDI16,
+    ADD16,
+    SUB16,
+    INC16,
+    CMP16,
+    MUL,
+    DIV,
+    MOD,
     _Label,
-    _Definition
+    _Definition,
+    _Alias,
+    _Assert,
+    _Error,
+    _Warning,
+    _Print,
+    _PrintNumber,
+    _Scratch,
+    _PortBase,
+    _Global,
+    _Extern,
+    _Var,
+    _Array,
+    _Struct,
+    _Field,
+    _EndStruct,
+    _Table,
+    _InitTables,
+    _Org,
+    _Align,
+    _Fill,
+    _Filler,
+    _Stack,
+    _Pair,
+    _Include,
+    _Sprite,
+    _If,
+    _Else,
+    _EndIf,
+    _While,
+    _EndWhile,
+    _Loop,
+    _EndLoop
+}
+
+/// What an instruction does with a register operand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite
+}
+
+impl Access {
+    pub fn reads(self) -> bool {
+        matches!(self, Access::Read | Access::ReadWrite)
+    }
+
+    pub fn writes(self) -> bool {
+        matches!(self, Access::Write | Access::ReadWrite)
+    }
+
+    /// Both accesses, for an operand that goes in two places
+    pub fn and(self, other: Access) -> Access {
+        if self == other { self } else { Access::ReadWrite }
+    }
+}
+
+/// Where an operand goes in the encoding. Registers go in `A`, `B` or `C`, and the offset of
+/// `LOD` and `STR` can be left out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    A(Access),
+    B(Access),
+    C(Access),
+    Immediate,
+    Condition,
+    Address,
+    Offset
+}
+
+impl Field {
+    pub fn access(&self) -> Option<Access> {
+        match self {
+            Field::A(access) | Field::B(access) | Field::C(access) => Some(*access),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::A(_) | Field::B(_) | Field::C(_) => "register",
+            Field::Immediate => "immediate",
+            Field::Condition => "condition",
+            Field::Address => "address",
+            Field::Offset => "offset"
+        }
+    }
+}
+
+impl Opcode {
+    /// Directives only exist at assemble time and never take up space in the binary. The same goes
+    /// for pseudo-instructions like `CMPI`, which the expander turns into real instructions
+    pub fn is_directive(&self) -> bool {
+        matches!(self,
+            Opcode::CMPI | Opcode::SUBI | Opcode::ANDI | Opcode::XORI |
+            Opcode::PUSH | Opcode::POP | Opcode::PUSHALL | Opcode::POPALL |
+            Opcode::LDI16 | Opcode::ADD16 | Opcode::SUB16 | Opcode::INC16 | Opcode::CMP16 |
+            Opcode::MUL | Opcode::DIV | Opcode::MOD |
+            Opcode::_Label | Opcode::_Definition | Opcode::_Alias |
+            Opcode::_Assert | Opcode::_Error | Opcode::_Warning |
+            Opcode::_Print | Opcode::_PrintNumber | Opcode::_Scratch | Opcode::_PortBase |
+            Opcode::_Global | Opcode::_Extern | Opcode::_Var | Opcode::_Array |
+            Opcode::_Struct | Opcode::_Field | Opcode::_EndStruct |
+            Opcode::_Table | Opcode::_InitTables |
+            Opcode::_Org | Opcode::_Align | Opcode::_Fill | Opcode::_Filler |
+            Opcode::_Stack | Opcode::_Pair | Opcode::_Include | Opcode::_Sprite |
+            Opcode::_If | Opcode::_Else | Opcode::_EndIf | Opcode::_While | Opcode::_EndWhile |
+            Opcode::_Loop | Opcode::_EndLoop
+        )
+    }
+
+    /// Directives that give a name to a value, which the evaluator looks up like any definition
+    pub fn is_definition(&self) -> bool {
+        matches!(self,
+            Opcode::_Definition | Opcode::_Var | Opcode::_Array | Opcode::_Field | Opcode::_EndStruct |
+            Opcode::_Table | Opcode::_Stack
+        )
+    }
+
+    /// Directives without operands, which a label of the same name could not be told apart from
+    pub fn is_bare_directive(&self) -> bool {
+        matches!(self,
+            Opcode::_EndStruct | Opcode::_InitTables |
+            Opcode::_Else | Opcode::_EndIf | Opcode::_EndWhile | Opcode::_EndLoop
+        )
+    }
+
+    /// What each operand of a real instruction is, in order. The parser, the encoder and the
+    /// register allocator all go by this. Pseudo-instructions have none, their built-in alias has
+    pub fn fields(&self) -> Option<&'static [Field]> {
+        use Access::*;
+        use Field::*;
+        match self {
+            Opcode::NOP | Opcode::HLT | Opcode::RET => Some(&[]),
+            Opcode::ADD | Opcode::SUB | Opcode::NOR | Opcode::AND | Opcode::XOR => Some(&[A(Read), B(Read), C(Write)]),
+            Opcode::RSH => Some(&[A(Read), C(Write)]),
+            Opcode::LDI => Some(&[A(Write), Immediate]),
+            Opcode::ADI => Some(&[A(ReadWrite), Immediate]),
+            Opcode::JMP | Opcode::CAL => Some(&[Address]),
+            Opcode::BRH => Some(&[Condition, Address]),
+            Opcode::LOD => Some(&[A(Read), B(Write), Offset]),
+            Opcode::STR => Some(&[A(Read), B(Read), Offset]),
+            _ => None
+        }
+    }
+
+    /// Directives that start with a `.`, e.g. `.assert`
+    pub fn from_directive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "assert" => Some(Opcode::_Assert),
+            "error" => Some(Opcode::_Error),
+            "warning" => Some(Opcode::_Warning),
+            "print" => Some(Opcode::_Print),
+            "print_reg_number" => Some(Opcode::_PrintNumber),
+            "scratch" => Some(Opcode::_Scratch),
+            "port_base" => Some(Opcode::_PortBase),
+            "global" => Some(Opcode::_Global),
+            "extern" => Some(Opcode::_Extern),
+            "var" => Some(Opcode::_Var),
+            "array" => Some(Opcode::_Array),
+            "struct" => Some(Opcode::_Struct),
+            "field" => Some(Opcode::_Field),
+            "endstruct" => Some(Opcode::_EndStruct),
+            "table" => Some(Opcode::_Table),
+            "init_tables" => Some(Opcode::_InitTables),
+            "org" => Some(Opcode::_Org),
+            "align" => Some(Opcode::_Align),
+            "fill" => Some(Opcode::_Fill),
+            "filler" => Some(Opcode::_Filler),
+            "stack" => Some(Opcode::_Stack),
+            "pair" => Some(Opcode::_Pair),
+            "include" => Some(Opcode::_Include),
+            "sprite" => Some(Opcode::_Sprite),
+            "if" => Some(Opcode::_If),
+            "else" => Some(Opcode::_Else),
+            "endif" => Some(Opcode::_EndIf),
+            "while" => Some(Opcode::_While),
+            "endwhile" => Some(Opcode::_EndWhile),
+            "loop" => Some(Opcode::_Loop),
+            "endloop" => Some(Opcode::_EndLoop),
+            _ => None
+        }
+    }
 }
 
 impl Display for Opcode {
@@ -57,8 +255,56 @@ impl Display for Opcode {
             Opcode::DEC => "dec",
             Opcode::NOT => "not",
             Opcode::NEG => "neg",
+            Opcode::CMPI => "cmpi",
+            Opcode::SUBI => "subi",
+            Opcode::ANDI => "andi",
+            Opcode::XORI => "xori",
+            Opcode::PUSH => "push",
+            Opcode::POP => "pop",
+            Opcode::PUSHALL => "pushall",
+            Opcode::POPALL => "popall",
+            Opcode::LDI16 => "ldi16",
+            Opcode::ADD16 => "add16",
+            Opcode::SUB16 => "sub16",
+            Opcode::INC16 => "inc16",
+            Opcode::CMP16 => "cmp16",
+            Opcode::MUL => "mul",
+            Opcode::DIV => "div",
+            Opcode::MOD => "mod",
             Opcode::_Label => ".",
-            Opcode::_Definition => "define"
+            Opcode::_Definition => "define",
+            Opcode::_Alias => "alias",
+            Opcode::_Assert => ".assert",
+            Opcode::_Error => ".error",
+            Opcode::_Warning => ".warning",
+            Opcode::_Print => ".print",
+            Opcode::_PrintNumber => ".print_reg_number",
+            Opcode::_Scratch => ".scratch",
+            Opcode::_PortBase => ".port_base",
+            Opcode::_Global => ".global",
+            Opcode::_Extern => ".extern",
+            Opcode::_Var => ".var",
+            Opcode::_Array => ".array",
+            Opcode::_Struct => ".struct",
+            Opcode::_Field => ".field",
+            Opcode::_EndStruct => ".endstruct",
+            Opcode::_Table => ".table",
+            Opcode::_InitTables => ".init_tables",
+            Opcode::_Org => ".org",
+            Opcode::_Align => ".align",
+            Opcode::_Fill => ".fill",
+            Opcode::_Filler => ".filler",
+            Opcode::_Stack => ".stack",
+            Opcode::_Pair => ".pair",
+            Opcode::_Include => ".include",
+            Opcode::_Sprite => ".sprite",
+            Opcode::_If => ".if",
+            Opcode::_Else => ".else",
+            Opcode::_EndIf => ".endif",
+            Opcode::_While => ".while",
+            Opcode::_EndWhile => ".endwhile",
+            Opcode::_Loop => ".loop",
+            Opcode::_EndLoop => ".endloop"
         };
         write!(f, "{}", text)
     }
@@ -92,6 +338,22 @@ impl FromStr for Opcode {
             "dec" => Ok(Opcode::DEC),
             "not" => Ok(Opcode::NOT),
             "neg" => Ok(Opcode::NEG),
+            "cmpi" => Ok(Opcode::CMPI),
+            "subi" => Ok(Opcode::SUBI),
+            "andi" => Ok(Opcode::ANDI),
+            "xori" => Ok(Opcode::XORI),
+            "push" => Ok(Opcode::PUSH),
+            "pop" => Ok(Opcode::POP),
+            "pushall" => Ok(Opcode::PUSHALL),
+            "popall" => Ok(Opcode::POPALL),
+            "ldi16" => Ok(Opcode::LDI16),
+            "add16" => Ok(Opcode::ADD16),
+            "sub16" => Ok(Opcode::SUB16),
+            "inc16" => Ok(Opcode::INC16),
+            "cmp16" => Ok(Opcode::CMP16),
+            "mul" => Ok(Opcode::MUL),
+            "div" => Ok(Opcode::DIV),
+            "mod" => Ok(Opcode::MOD),
             _ => Err(())
         }
     }
@@ -0,0 +1,62 @@
+use std::fmt::Display;
+use crate::architecture::batpu2::KEYWORDS;
+use crate::architecture::batpu2::operand::register::Register;
+
+/// A name for a register, e.g. `alias cursor_x r1`. Virtual registers like `%count` are names
+/// too, but the register allocator picks their register
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterAlias {
+    pub name: String,
+    pub register: Option<Register>,
+    /// Only valid until the next global label
+    pub local: bool
+}
+
+impl RegisterAlias {
+    pub fn new_def(name: &str, register: Register, local: bool) -> Option<RegisterAlias> {
+        if name.is_empty() || KEYWORDS.contains(&name.to_lowercase().as_str()) {
+            None
+        } else {
+            Some(RegisterAlias {
+                name: name.to_string(),
+                register: Some(register),
+                local
+            })
+        }
+    }
+
+    pub fn new_opr(name: &str) -> Option<RegisterAlias> {
+        if name.is_empty() || KEYWORDS.contains(&name.to_lowercase().as_str()) {
+            None
+        } else {
+            Some(RegisterAlias {
+                name: name.to_string(),
+                register: None,
+                local: false
+            })
+        }
+    }
+
+    /// `name` is written without its `%`
+    pub fn new_virtual(name: &str) -> RegisterAlias {
+        RegisterAlias {
+            name: format!("%{name}"),
+            register: None,
+            local: true
+        }
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        self.name.starts_with('%')
+    }
+}
+
+impl Display for RegisterAlias {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.register {
+            Some(register) => write!(f, "{} ({})", self.name, register),
+            None if self.is_virtual() => write!(f, "{}", self.name),
+            None => write!(f, "{} (NULL)", self.name)
+        }
+    }
+}
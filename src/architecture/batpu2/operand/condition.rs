@@ -32,4 +32,80 @@ impl Display for Condition {
             Condition::C0 => write!(f, "lt")
         }
     }
-}
\ No newline at end of file
+}
+impl Condition {
+    /// The condition that holds exactly when this one does not
+    pub fn negate(self) -> Condition {
+        match self {
+            Condition::Z1 => Condition::Z0,
+            Condition::Z0 => Condition::Z1,
+            Condition::C1 => Condition::C0,
+            Condition::C0 => Condition::C1
+        }
+    }
+
+    /// The branch mnemonic the listing shows for generated branches
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Condition::Z1 => "beq",
+            Condition::Z0 => "bne",
+            Condition::C1 => "bge",
+            Condition::C0 => "blt"
+        }
+    }
+}
+
+/// How `.if` and `.while` compare two unsigned values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    GreaterOrEqual,
+    Greater,
+    LessOrEqual
+}
+
+impl Comparison {
+    /// The condition that `CMP a, b` leaves in the flags when `a` compares true to `b`. The flags
+    /// cannot tell greater from less or equal, so those compare `b` to `a` instead
+    pub fn condition(self) -> (Condition, bool) {
+        match self {
+            Comparison::Equal => (Condition::Z1, false),
+            Comparison::NotEqual => (Condition::Z0, false),
+            Comparison::Less => (Condition::C0, false),
+            Comparison::GreaterOrEqual => (Condition::C1, false),
+            Comparison::Greater => (Condition::C0, true),
+            Comparison::LessOrEqual => (Condition::C1, true)
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "==" | "=" | "eq" => Ok(Comparison::Equal),
+            "!=" | "ne" => Ok(Comparison::NotEqual),
+            "<" | "lt" => Ok(Comparison::Less),
+            ">=" | "ge" => Ok(Comparison::GreaterOrEqual),
+            ">" | "gt" => Ok(Comparison::Greater),
+            "<=" | "le" => Ok(Comparison::LessOrEqual),
+            _ => Err(())
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Comparison::Equal => write!(f, "=="),
+            Comparison::NotEqual => write!(f, "!="),
+            Comparison::Less => write!(f, "<"),
+            Comparison::GreaterOrEqual => write!(f, ">="),
+            Comparison::Greater => write!(f, ">"),
+            Comparison::LessOrEqual => write!(f, "<=")
+        }
+    }
+}
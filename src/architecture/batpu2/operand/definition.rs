@@ -1,23 +1,40 @@
 use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 use crate::architecture::batpu2::KEYWORDS;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Definition {
     pub name: String,
-    pub value: Option<i16>
+    pub value: Option<i16>,
+    /// Set with `define NAME:kind value`, untyped definitions can be used anywhere their value fits
+    pub kind: Option<DefinitionKind>
+}
+
+/// What a typed definition may be used as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DefinitionKind {
+    Immediate,
+    Offset,
+    Port,
+    /// A byte address in program memory
+    Address,
+    Register
 }
 
 impl Definition {
-    pub fn new_def(name: &str, value: i16) -> Option<Definition> {
+    pub fn new_def(name: &str, value: i16, kind: Option<DefinitionKind>) -> Option<Definition> {
         if name.is_empty() || KEYWORDS.contains(&name.to_lowercase().as_str()) {
             return None;
         }
-        if value < -128 || value > 255 {
+        let range = kind.map_or(DefinitionKind::Immediate.range(), |k| k.range());
+        if !range.contains(&value) {
             None
         } else {
             Some(Definition{
                 name: name.to_string(),
-                value: Some(value)
+                value: Some(value),
+                kind
             })
         }
     }
@@ -28,12 +45,41 @@ impl Definition {
         } else {
             Some(Definition {
                 name: name.to_string(),
-                value: None
+                value: None,
+                kind: None
             })
         }
     }
 }
 
+impl DefinitionKind {
+    /// The values a definition of this kind can hold
+    pub fn range(&self) -> RangeInclusive<i16> {
+        match self {
+            DefinitionKind::Immediate => -128..=255,
+            DefinitionKind::Offset => -8..=7,
+            DefinitionKind::Port => 240..=255,
+            DefinitionKind::Address => 0..=0x07FE,
+            DefinitionKind::Register => 0..=15
+        }
+    }
+}
+
+impl FromStr for DefinitionKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "imm" | "immediate" => Ok(DefinitionKind::Immediate),
+            "offset" => Ok(DefinitionKind::Offset),
+            "port" => Ok(DefinitionKind::Port),
+            "addr" | "address" => Ok(DefinitionKind::Address),
+            "reg" | "register" => Ok(DefinitionKind::Register),
+            _ => Err(())
+        }
+    }
+}
+
 impl Display for Definition {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self.value {
@@ -41,4 +87,17 @@ impl Display for Definition {
             None => write!(f, "{} (NULL)", self.name)
         }
     }
-}
\ No newline at end of file
+}
+
+impl Display for DefinitionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let text = match self {
+            DefinitionKind::Immediate => "immediate",
+            DefinitionKind::Offset => "offset",
+            DefinitionKind::Port => "port",
+            DefinitionKind::Address => "address",
+            DefinitionKind::Register => "register"
+        };
+        write!(f, "{}", text)
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt::Display;
+use crate::architecture::batpu2::operand::definition::Definition;
+use crate::architecture::batpu2::operand::immediate::Address;
+use crate::architecture::batpu2::operand::label::Label;
+
+/// An arithmetic expression over labels, definitions, numbers and the location counter (`$`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub node: Node,
+    value: Option<i32>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Number(i32),
+    Label(Label),
+    Def(Definition),
+    /// `$`, the address of the current instruction
+    Here,
+    Unary(UnaryOperator, Box<Node>),
+    Binary(Box<Node>, Operator, Box<Node>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not
+}
+
+/// Binary operators, comparisons evaluate to 1 (true) or 0 (false)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Mul,
+    Div,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or
+}
+
+impl Expression {
+    pub fn new(node: Node) -> Expression {
+        Expression {
+            node,
+            value: None
+        }
+    }
+
+    pub fn labels_mut(&mut self) -> Vec<&mut Label> {
+        let mut labels = Vec::new();
+        self.node.collect_labels(&mut labels);
+        labels
+    }
+
+    pub fn definitions_mut(&mut self) -> Vec<&mut Definition> {
+        let mut definitions = Vec::new();
+        self.node.collect_definitions(&mut definitions);
+        definitions
+    }
+
+    /// Computes the value of the expression, `here` being the byte address of the instruction using it.
+    /// Returns None if a label (or `$`) does not have an address yet, a definition is not bound
+    /// or the arithmetic overflows
+    pub fn evaluate(&mut self, here: Option<Address>) -> Option<i32> {
+        self.value = self.node.evaluate(here);
+        self.value
+    }
+
+    pub fn value(&self) -> Option<i32> {
+        self.value
+    }
+}
+
+impl Node {
+    fn collect_labels<'a>(&'a mut self, labels: &mut Vec<&'a mut Label>) {
+        match self {
+            Node::Label(label) => labels.push(label),
+            Node::Unary(_, node) => node.collect_labels(labels),
+            Node::Binary(left, _, right) => {
+                left.collect_labels(labels);
+                right.collect_labels(labels);
+            },
+            Node::Number(_) | Node::Def(_) | Node::Here => {}
+        }
+    }
+
+    fn collect_definitions<'a>(&'a mut self, definitions: &mut Vec<&'a mut Definition>) {
+        match self {
+            Node::Def(def) => definitions.push(def),
+            Node::Unary(_, node) => node.collect_definitions(definitions),
+            Node::Binary(left, _, right) => {
+                left.collect_definitions(definitions);
+                right.collect_definitions(definitions);
+            },
+            Node::Number(_) | Node::Label(_) | Node::Here => {}
+        }
+    }
+
+    fn evaluate(&self, here: Option<Address>) -> Option<i32> {
+        match self {
+            Node::Number(n) => Some(*n),
+            Node::Label(label) => label.get_address().map(|a| (a.value() << 1) as i32),
+            Node::Def(def) => def.value.map(|v| v as i32),
+            Node::Here => here.map(|a| (a.value() << 1) as i32),
+            Node::Unary(op, node) => {
+                let value = node.evaluate(here)?;
+                match op {
+                    UnaryOperator::Negate => value.checked_neg(),
+                    UnaryOperator::Not => Some(!value)
+                }
+            },
+            Node::Binary(left, op, right) => {
+                let left = left.evaluate(here)?;
+                let right = right.evaluate(here)?;
+                match op {
+                    Operator::Mul => left.checked_mul(right),
+                    Operator::Div => left.checked_div(right),
+                    Operator::Add => left.checked_add(right),
+                    Operator::Sub => left.checked_sub(right),
+                    Operator::Shl => left.checked_shl(right as u32),
+                    Operator::Shr => left.checked_shr(right as u32),
+                    Operator::Lt => Some((left < right) as i32),
+                    Operator::Le => Some((left <= right) as i32),
+                    Operator::Gt => Some((left > right) as i32),
+                    Operator::Ge => Some((left >= right) as i32),
+                    Operator::Eq => Some((left == right) as i32),
+                    Operator::Ne => Some((left != right) as i32),
+                    Operator::And => Some(left & right),
+                    Operator::Xor => Some(left ^ right),
+                    Operator::Or => Some(left | right)
+                }
+            }
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.value {
+            Some(value) => write!(f, "{} ({})", self.node, value),
+            None => write!(f, "{}", self.node)
+        }
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Node::Number(n) => write!(f, "{}", n),
+            Node::Label(label) => write!(f, "{}", label),
+            Node::Def(def) => write!(f, "{}", def.name),
+            Node::Here => write!(f, "$"),
+            Node::Unary(op, node) => write!(f, "{}{}", op, node),
+            Node::Binary(left, op, right) => {
+                // Only nested operations need parentheses to keep their meaning
+                match **left {
+                    Node::Binary(..) => write!(f, "({})", left)?,
+                    _ => write!(f, "{}", left)?
+                }
+                write!(f, " {} ", op)?;
+                match **right {
+                    Node::Binary(..) => write!(f, "({})", right),
+                    _ => write!(f, "{}", right)
+                }
+            }
+        }
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UnaryOperator::Negate => write!(f, "-"),
+            UnaryOperator::Not => write!(f, "~")
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let text = match self {
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Shl => "<<",
+            Operator::Shr => ">>",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::And => "&",
+            Operator::Xor => "^",
+            Operator::Or => "|"
+        };
+        write!(f, "{}", text)
+    }
+}
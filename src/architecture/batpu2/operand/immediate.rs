@@ -11,7 +11,7 @@ pub struct Offset(i8);
 
 impl Immediate {
     pub fn new(value: i16) -> Option<Self> {
-        if value < -128 || value > 255 {
+        if !(-128..=255).contains(&value) {
             None
         } else {
             Some(Immediate(value))
@@ -46,8 +46,11 @@ impl Address {
 }
 
 impl Offset {
+    pub const MIN: i8 = -8;
+    pub const MAX: i8 = 7;
+
     pub fn new(value: i8) -> Option<Self> {
-        if value < -8 || value > 7 {
+        if !(Offset::MIN..=Offset::MAX).contains(&value) {
             None
         } else {
             Some(Offset(value))
@@ -17,11 +17,11 @@ impl Label {
     }
 
     pub fn set_address(&mut self, instruction: &Instruction) {
-        self.address = instruction.location.clone();
+        self.address = instruction.location;
     }
 
     pub fn get_address(&self) -> Option<Address> {
-        self.address.clone()
+        self.address
     }
 }
 
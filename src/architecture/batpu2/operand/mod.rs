@@ -1,14 +1,21 @@
 use std::fmt::Display;
-use crate::architecture::batpu2::operand::condition::Condition;
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::operand::alias::RegisterAlias;
+use crate::architecture::batpu2::operand::condition::{Comparison, Condition};
 use crate::architecture::batpu2::operand::definition::Definition;
+use crate::architecture::batpu2::operand::expression::Expression;
 use crate::architecture::batpu2::operand::immediate::{Address, Immediate, Offset};
 use crate::architecture::batpu2::operand::label::Label;
+use crate::architecture::batpu2::operand::pair::RegisterPair;
 use crate::architecture::batpu2::operand::port::Port;
 use crate::architecture::batpu2::operand::register::Register;
 
+pub mod alias;
 pub mod condition;
 pub mod definition;
+pub mod expression;
 pub mod label;
+pub mod pair;
 pub mod immediate;
 pub mod register;
 pub mod port;
@@ -34,7 +41,41 @@ pub enum Operand {
     /// Used in immediate operations as constants
     Port(Port),
     /// Used as a label definition
-    Name(String)
+    Name(String),
+    /// Used in jump and branch operations, resolved after layout
+    Expr(Expression),
+    /// Used anywhere a register is, or as an alias definition
+    Alias(RegisterAlias),
+    /// Used as the message of diagnostic directives
+    Text(String),
+    /// Used as the repeat count of `.fill` and the alignment of `.align`
+    Count(u16),
+    /// Used as the instruction that `.fill` and `.filler` repeat
+    Instr(Box<Instruction>),
+    /// Used in 16-bit operations, or as a pair definition
+    Pair(RegisterPair),
+    /// Used in the conditions of `.if` and `.while`
+    Compare(Comparison)
+}
+
+impl Operand {
+    /// The register of a register operand or of a bound alias
+    pub fn register(&self) -> Option<Register> {
+        match self {
+            Operand::Reg(reg) => Some(*reg),
+            Operand::Alias(alias) => alias.register,
+            _ => None
+        }
+    }
+
+    /// Every label used by this operand, including the ones inside expressions
+    pub fn labels_mut(&mut self) -> Vec<&mut Label> {
+        match self {
+            Operand::Label(label) => vec![label],
+            Operand::Expr(expr) => expr.labels_mut(),
+            _ => Vec::new()
+        }
+    }
 }
 
 impl From<Register> for Operand {
@@ -91,6 +132,18 @@ impl From<char> for Operand {
     }
 }
 
+impl From<RegisterAlias> for Operand {
+    fn from(alias: RegisterAlias) -> Self {
+        Operand::Alias(alias)
+    }
+}
+
+impl From<Expression> for Operand {
+    fn from(expr: Expression) -> Self {
+        Operand::Expr(expr)
+    }
+}
+
 impl From<Offset> for Operand {
     fn from(n: Offset) -> Self {
         Operand::Offset(n)
@@ -108,8 +161,15 @@ impl Display for Operand {
             Operand::Name(name) => write!(f, "{}", name),
             Operand::Def(def) => write!(f, "{}", def),
             Operand::Port(port) => write!(f, "{}", port),
-            Operand::Char(c) => write!(f, "'{}' (0x{:02X})", c, *c as u8),
-            Operand::Offset(off) => write!(f, "{}", off)
+            Operand::Char(c) => write!(f, "'{}'", c),
+            Operand::Offset(off) => write!(f, "{}", off),
+            Operand::Expr(expr) => write!(f, "{}", expr),
+            Operand::Alias(alias) => write!(f, "{}", alias),
+            Operand::Text(text) => write!(f, "\"{}\"", text),
+            Operand::Count(n) => write!(f, "{}", n),
+            Operand::Instr(instruction) => write!(f, "{}", instruction),
+            Operand::Pair(pair) => write!(f, "{}", pair),
+            Operand::Compare(comparison) => write!(f, "{}", comparison)
         }
     }
 }
\ No newline at end of file
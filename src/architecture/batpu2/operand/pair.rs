@@ -0,0 +1,60 @@
+use std::fmt::Display;
+use crate::architecture::batpu2::KEYWORDS;
+use crate::architecture::batpu2::operand::register::Register;
+
+/// Two registers used as one 16-bit value, high byte first. Either named with
+/// `.pair score r2, r3` or written out as `r2:r3`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterPair {
+    pub name: String,
+    pub registers: Option<(Register, Register)>
+}
+
+impl RegisterPair {
+    pub fn new_def(name: &str, high: Register, low: Register) -> Option<RegisterPair> {
+        if name.is_empty() || KEYWORDS.contains(&name.to_lowercase().as_str()) {
+            None
+        } else {
+            Some(RegisterPair {
+                name: name.to_string(),
+                registers: Some((high, low))
+            })
+        }
+    }
+
+    pub fn new_opr(name: &str) -> Option<RegisterPair> {
+        if name.is_empty() || KEYWORDS.contains(&name.to_lowercase().as_str()) {
+            None
+        } else {
+            Some(RegisterPair {
+                name: name.to_string(),
+                registers: None
+            })
+        }
+    }
+
+    pub fn new_literal(high: Register, low: Register) -> RegisterPair {
+        RegisterPair {
+            name: format!("{}:{}", high, low),
+            registers: Some((high, low))
+        }
+    }
+
+    pub fn high(&self) -> Option<Register> {
+        self.registers.map(|(high, _)| high)
+    }
+
+    pub fn low(&self) -> Option<Register> {
+        self.registers.map(|(_, low)| low)
+    }
+}
+
+impl Display for RegisterPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.registers {
+            Some((high, low)) if self.name != format!("{}:{}", high, low) => write!(f, "{} ({}:{})", self.name, high, low),
+            Some(_) => write!(f, "{}", self.name),
+            None => write!(f, "{} (NULL)", self.name)
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::fmt::Display;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Register {
     R0, R1, R2, R3,
     R4, R5, R6, R7,
@@ -9,6 +9,15 @@ pub enum Register {
     R12, R13, R14, R15
 }
 
+impl Register {
+    pub const ALL: [Register; 16] = [
+        Register::R0, Register::R1, Register::R2, Register::R3,
+        Register::R4, Register::R5, Register::R6, Register::R7,
+        Register::R8, Register::R9, Register::R10, Register::R11,
+        Register::R12, Register::R13, Register::R14, Register::R15
+    ];
+}
+
 impl Display for Register {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "r{}", *self as u8)
@@ -0,0 +1,48 @@
+use std::fmt::Display;
+use anyhow::Error;
+
+/// Every error found in one run, so they can all be fixed before building again
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub errors: Vec<Error>
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics {
+            errors: Vec::new()
+        }
+    }
+
+    pub fn push<E: Into<Error>>(&mut self, error: E) {
+        self.errors.push(error.into());
+    }
+
+    pub fn extend<E: Into<Error>>(&mut self, errors: Vec<E>) {
+        self.errors.extend(errors.into_iter().map(Into::into));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let [error] = self.errors.as_slice() {
+            return write!(f, "{}", error);
+        }
+
+        write!(f, "{} errors:", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
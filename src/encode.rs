@@ -1,59 +1,93 @@
 use thiserror::Error;
+use crate::architecture::batpu2::charset::Charset;
 use crate::architecture::batpu2::instruction::Instruction;
-use crate::architecture::batpu2::opcode::Opcode;
-use crate::architecture::batpu2::operand::immediate::{Immediate, Offset};
+use crate::architecture::batpu2::opcode::{Field, Opcode};
+use crate::architecture::batpu2::operand::alias::RegisterAlias;
+use crate::architecture::batpu2::operand::immediate::{Address, Immediate, Offset};
 use crate::architecture::batpu2::operand::label::Label;
 use crate::architecture::batpu2::operand::Operand;
-use crate::architecture::batpu2::operand::register::Register::R0;
+use crate::instructions::InstructionSet;
+use crate::source::SourceLocation;
 
 #[derive(Debug, Error)]
 pub enum EncodingError {
-    #[error("Value {value} is out of bounds for {length} bits")]
+    #[error("{location}: Value {value} is out of bounds for {length} bits")]
     ValueOutOfBounds {
+        location: SourceLocation,
         value: u16,
         length: u16
     },
-    #[error("Value {value} is too large for {length} bits")]
+    #[error("{location}: Value {value} is too large for {length} bits")]
     ValueOverflow {
+        location: SourceLocation,
         value: u16,
         length: u16
     },
-    #[error("Operand {found} is not a valid {expected}")]
+    #[error("{location}: Operand {found} is not a valid {expected}")]
     InvalidOperand {
+        location: SourceLocation,
         expected: String,
         found: Operand
     },
-    #[error("Definition {0} is not bound")]
-    UnboundDefinition(String),
-    #[error("Offset {0} is invalid (-8 <= offset <= 7)")]
-    InvalidOffset(i8),
-    #[error("Label {0} is invalid")]
-    InvalidLabel(Label)
+    #[error("{location}: Definition {name} is not bound")]
+    UnboundDefinition {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Offset {offset} is invalid (-8 <= offset <= 7)")]
+    InvalidOffset {
+        location: SourceLocation,
+        offset: i16
+    },
+    #[error("{location}: Label {label} is invalid")]
+    InvalidLabel {
+        location: SourceLocation,
+        label: Label
+    },
+    #[error("{location}: Character '{character}' cannot be shown on the character display")]
+    UnsupportedCharacter {
+        location: SourceLocation,
+        character: char
+    },
+    #[error("{location}: {opcode} was never expanded into instructions that can be encoded")]
+    Unexpanded {
+        location: SourceLocation,
+        opcode: Opcode
+    }
 }
 
 pub struct InstructionEncoder {
     encoding: Option<u16>,
-    total: u16
+    total: u16,
+    /// The instruction being encoded, for errors
+    location: SourceLocation,
+    pub charset: Charset
+}
+
+impl Default for InstructionEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InstructionEncoder {
     pub fn new() -> InstructionEncoder {
         InstructionEncoder {
             encoding: None,
-            total: 0
+            total: 0,
+            location: SourceLocation::default(),
+            charset: Charset::default()
         }
     }
 
     pub fn encode_program(&mut self, program: &mut [Instruction]) -> Result<(), EncodingError> {
-        for i in 0..program.len() {
+        for instruction in program.iter_mut() {
             self.encoding = Some(0);
-            match program[i].opcode {
-                Opcode::_Definition | Opcode::_Label => {},
-                _ => {
-                    trace!("Encoding instruction: {}", program[i]);
-                    self.encode_instruction(&mut program[i])?;
-                    program[i].encoding = self.encoding;
-                }
+            if !instruction.opcode.is_directive() {
+                trace!("Encoding instruction: {}", instruction);
+                self.location = instruction.source.clone();
+                self.encode_instruction(instruction)?;
+                instruction.encoding = self.encoding;
             }
         }
         debug!("Encoded {} instructions", self.total);
@@ -61,115 +95,32 @@ impl InstructionEncoder {
     }
 
     fn encode_instruction(&mut self, instruction: &mut Instruction) -> Result<(), EncodingError> {
-        let opcode = &instruction.opcode;
-        match opcode {
-            Opcode::NOP | Opcode::HLT | Opcode::RET => {
-                self.encode_opcode(&instruction.opcode)?;
-                self.total += 1;
-                Ok(())
-            },
-            Opcode::ADD | Opcode::SUB | Opcode::NOR | Opcode::AND | Opcode::XOR => {
-                self.encode_opcode(&instruction.opcode)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_b(&instruction.operands[1])?;
-                self.encode_c(&instruction.operands[2])?;
-                self.total += 1;
-                Ok(())
-            },
-            Opcode::RSH => {
-                self.encode_opcode(&instruction.opcode)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_c(&instruction.operands[1])?;
-                self.total += 1;
-                Ok(())
-            },
-            Opcode::LDI | Opcode::ADI => {
-                self.encode_opcode(&instruction.opcode)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_imm(&instruction.operands[1])?;
-                self.total += 1;
-                Ok(())
-            },
-            Opcode::JMP | Opcode::CAL => {
-                self.encode_opcode(&instruction.opcode)?;
-                self.encode_addr(&instruction.operands[0])?;
-                self.total += 1;
-                Ok(())
-            },
-            Opcode::BRH => {
-                self.encode_opcode(&instruction.opcode)?;
-                self.encode_cond(&instruction.operands[0])?;
-                self.encode_addr(&instruction.operands[1])?;
-                self.total += 1;
-                Ok(())
-            },
-            Opcode::LOD | Opcode::STR => {
-                self.encode_opcode(&instruction.opcode)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_b(&instruction.operands[1])?;
-                self.encode_offset(instruction.operands.get(2))?;
-                self.total += 1;
-                Ok(())
-            }
-            Opcode::CMP => {
-                self.encode_opcode(&Opcode::SUB)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_b(&instruction.operands[1])?;
-                self.encode_c(&Operand::Reg(R0))?;
-                self.total += 1;
-                Ok(())
-            }
-            Opcode::MOV => {
-                self.encode_opcode(&Opcode::ADD)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_b(&Operand::Reg(R0))?;
-                self.encode_c(&instruction.operands[1])?;
-                self.total += 1;
-                Ok(())
-            }
-            Opcode::LSH => {
-                self.encode_opcode(&Opcode::ADD)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_b(&instruction.operands[0])?;
-                self.encode_c(&instruction.operands[1])?;
-                self.total += 1;
-                Ok(())
-            }
-            Opcode::INC => {
-                self.encode_opcode(&Opcode::ADI)?;
-                self.encode_a(&instruction.operands[0])?;
-                let imm = Immediate::new(1).unwrap();
-                self.encode_imm(&Operand::from(imm))?;
-                self.total += 1;
-                Ok(())
-            }
-            Opcode::DEC => {
-                self.encode_opcode(&Opcode::ADI)?;
-                self.encode_a(&instruction.operands[0])?;
-                let imm = Immediate::new(-1).unwrap();
-                self.encode_imm(&Operand::from(imm))?;
-                self.total += 1;
-                Ok(())
-            }
-            Opcode::NOT => {
-                self.encode_opcode(&Opcode::NOR)?;
-                self.encode_a(&instruction.operands[0])?;
-                self.encode_b(&Operand::Reg(R0))?;
-                self.encode_c(&instruction.operands[1])?;
-                self.total += 1;
-                Ok(())
-            }
-            Opcode::NEG => {
-                self.encode_opcode(&Opcode::SUB)?;
-                self.encode_a(&Operand::Reg(R0))?;
-                self.encode_b(&instruction.operands[0])?;
-                self.encode_c(&instruction.operands[1])?;
-                self.total += 1;
-                Ok(())
+        let opcode = instruction.opcode;
+        if let Some(fields) = opcode.fields() {
+            self.encode_opcode(&opcode)?;
+            for (i, field) in fields.iter().enumerate() {
+                match field {
+                    Field::A(_) => self.encode_a(&instruction.operands[i])?,
+                    Field::B(_) => self.encode_b(&instruction.operands[i])?,
+                    Field::C(_) => self.encode_c(&instruction.operands[i])?,
+                    Field::Immediate => self.encode_imm(&instruction.operands[i])?,
+                    Field::Condition => self.encode_cond(&instruction.operands[i])?,
+                    Field::Address => self.encode_addr(&instruction.operands[i])?,
+                    Field::Offset => self.encode_offset(instruction.operands.get(i))?
+                }
             }
-            _ => {
-                self.encoding = None;
-                Ok(())
+            self.total += 1;
+            return Ok(());
+        }
+
+        // Pseudo-instructions are encoded as what their built-in alias stands for
+        match InstructionSet::builtin(&opcode.to_string()) {
+            Some(alias) => self.encode_instruction(&mut alias.expand(&instruction.operands)),
+            None => {
+                Err(EncodingError::Unexpanded {
+                    location: self.location.clone(),
+                    opcode
+                })
             }
         }
     }
@@ -181,12 +132,13 @@ impl InstructionEncoder {
 
     fn encode_a(&mut self, operand: &Operand) -> Result<(), EncodingError> {
         match operand {
-            Operand::Reg(reg) => {
+            Operand::Reg(reg) | Operand::Alias(RegisterAlias { register: Some(reg), .. }) => {
                 self.encode_bits(8, 4, *reg as u16)?;
                 Ok(())
             },
             _ => {
-                Err(EncodingError::InvalidOperand{
+                Err(EncodingError::InvalidOperand {
+                    location: self.location.clone(),
                     expected: "register".to_string(),
                     found: operand.clone(),
                 })
@@ -196,12 +148,13 @@ impl InstructionEncoder {
 
     fn encode_b(&mut self, operand: &Operand) -> Result<(), EncodingError> {
         match operand {
-            Operand::Reg(reg) => {
+            Operand::Reg(reg) | Operand::Alias(RegisterAlias { register: Some(reg), .. }) => {
                 self.encode_bits(4, 4, *reg as u16)?;
                 Ok(())
             },
             _ => {
-                Err(EncodingError::InvalidOperand{
+                Err(EncodingError::InvalidOperand {
+                    location: self.location.clone(),
                     expected: "register".to_string(),
                     found: operand.clone(),
                 })
@@ -211,12 +164,13 @@ impl InstructionEncoder {
 
     fn encode_c(&mut self, operand: &Operand) -> Result<(), EncodingError> {
         match operand {
-            Operand::Reg(reg) => {
+            Operand::Reg(reg) | Operand::Alias(RegisterAlias { register: Some(reg), .. }) => {
                 self.encode_bits(0, 4, *reg as u16)?;
                 Ok(())
             },
             _ => {
-                Err(EncodingError::InvalidOperand{
+                Err(EncodingError::InvalidOperand {
+                    location: self.location.clone(),
                     expected: "register".to_string(),
                     found: operand.clone(),
                 })
@@ -234,7 +188,10 @@ impl InstructionEncoder {
                 let imm = match definition.value {
                     Some(i) => i,
                     None => {
-                        return Err(EncodingError::UnboundDefinition(definition.name.clone()));
+                        return Err(EncodingError::UnboundDefinition {
+                            location: self.location.clone(),
+                            name: definition.name.clone()
+                        });
                     }
                 };
                 self.encode_bits(0, 8, imm as u16)?;
@@ -245,11 +202,40 @@ impl InstructionEncoder {
                 Ok(())
             },
             Operand::Char(ch) => {
-                self.encode_bits(0, 8, *ch as u16)?;
+                let code = match self.charset.encode(*ch) {
+                    Some(c) => c,
+                    None => {
+                        return Err(EncodingError::UnsupportedCharacter {
+                            location: self.location.clone(),
+                            character: *ch
+                        });
+                    }
+                };
+                self.encode_bits(0, 8, code as u16)?;
                 Ok(())
             },
+            Operand::Expr(expr) => {
+                let imm = expr.value()
+                    .and_then(|v| i16::try_from(v).ok())
+                    .and_then(Immediate::new);
+
+                match imm {
+                    Some(imm) => {
+                        self.encode_bits(0, 8, imm.value() as u16)?;
+                        Ok(())
+                    },
+                    None => {
+                        Err(EncodingError::InvalidOperand {
+                            location: self.location.clone(),
+                            expected: "immediate".to_string(),
+                            found: operand.clone(),
+                        })
+                    }
+                }
+            },
             _ => {
-                Err(EncodingError::InvalidOperand{
+                Err(EncodingError::InvalidOperand {
+                    location: self.location.clone(),
                     expected: "immediate".to_string(),
                     found: operand.clone(),
                 })
@@ -264,7 +250,8 @@ impl InstructionEncoder {
                 Ok(())
             },
             _ => {
-                Err(EncodingError::InvalidOperand{
+                Err(EncodingError::InvalidOperand {
+                    location: self.location.clone(),
                     expected: "condition".to_string(),
                     found: operand.clone(),
                 })
@@ -285,15 +272,38 @@ impl InstructionEncoder {
                     Some(a) => a,
                     None => {
                         error!("Label {} does not have an address", o.name);
-                        return Err(EncodingError::InvalidLabel(label.clone()));
+                        return Err(EncodingError::InvalidLabel {
+                            location: self.location.clone(),
+                            label: label.clone()
+                        });
                     }
                 };
 
                 self.encode_bits(0, 10, a.value())?;
                 Ok(())
             },
+            Operand::Expr(expr) => {
+                let a = expr.value()
+                    .and_then(|v| u16::try_from(v).ok())
+                    .and_then(Address::new);
+
+                match a {
+                    Some(a) => {
+                        self.encode_bits(0, 10, a.value())?;
+                        Ok(())
+                    },
+                    None => {
+                        Err(EncodingError::InvalidOperand {
+                            location: self.location.clone(),
+                            expected: "address".to_string(),
+                            found: operand.clone(),
+                        })
+                    }
+                }
+            },
             _ => {
                 Err(EncodingError::InvalidOperand {
+                    location: self.location.clone(),
                     expected: "address".to_string(),
                     found: operand.clone(),
                 })
@@ -311,21 +321,29 @@ impl InstructionEncoder {
                 let imm = match def.value {
                     Some(i) => i,
                     None => {
-                        return Err(EncodingError::UnboundDefinition(def.name.clone()));
+                        return Err(EncodingError::UnboundDefinition {
+                            location: self.location.clone(),
+                            name: def.name.clone()
+                        });
                     }
                 };
-                match Offset::new(imm as i8) {
+                // Never truncate, 255 as an i8 would silently become an offset of -1
+                match i8::try_from(imm).ok().and_then(Offset::new) {
                     Some(i) => {
                         self.encode_bits(0, 4, i.encode() as u16)?;
                         Ok(())
                     },
                     None => {
-                        Err(EncodingError::InvalidOffset(imm as i8))
+                        Err(EncodingError::InvalidOffset {
+                            location: self.location.clone(),
+                            offset: imm
+                        })
                     }
                 }
             },
             Some(_) => {
-                Err(EncodingError::InvalidOperand{
+                Err(EncodingError::InvalidOperand {
+                    location: self.location.clone(),
                     expected: "optional immediate".to_string(),
                     found: operand.unwrap().clone(),
                 })
@@ -339,11 +357,19 @@ impl InstructionEncoder {
 
     fn encode_bits(&mut self, offset: u16, length: u16, value: u16) -> Result<(), EncodingError> {
         if offset + length > 16 {
-            return Err(EncodingError::ValueOutOfBounds { value, length });
+            return Err(EncodingError::ValueOutOfBounds {
+                location: self.location.clone(),
+                value,
+                length
+            });
         }
         if value >= 1 << length {
             debug!("Errored after {} instructions", self.total);
-            return Err(EncodingError::ValueOverflow{ value, length });
+            return Err(EncodingError::ValueOverflow {
+                location: self.location.clone(),
+                value,
+                length
+            });
         }
 
         let mask: u16 = ((1 << length) - 1) << offset;
@@ -1,29 +1,113 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use thiserror::Error;
 use crate::architecture::batpu2::instruction::Instruction;
-use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::PORTS;
+use crate::architecture::batpu2::opcode::{Field, Opcode};
+use crate::architecture::batpu2::operand::immediate::{Address, Immediate};
+use crate::architecture::batpu2::operand::definition::DefinitionKind;
 use crate::architecture::batpu2::operand::Operand;
+use crate::resolve::declared_names;
+use crate::source::SourceLocation;
+use crate::suggest::Suggestion;
 
-type Definitions = HashMap<String, i16>;
+/// Definitions are looked up by the file they are in and their name
+type Definitions = HashMap<(String, String), Defined>;
+
+/// A definition's value and kind, along with where it is defined for errors
+#[derive(Debug, Clone)]
+struct Defined {
+    value: i16,
+    kind: Option<DefinitionKind>,
+    source: SourceLocation
+}
+
+/// What a definition is used as, which decides the kinds and values it may have
+#[derive(Debug, Clone, Copy)]
+enum Usage {
+    Immediate,
+    Offset,
+    Expression
+}
 
 #[derive(Debug, Error)]
 pub enum EvaluatorError {
-    #[error("Unknown definition: {0}")]
-    UnknownDefinition(String),
+    #[error("{location}: Unknown definition: {name}{suggestion}")]
+    UnknownDefinition {
+        location: SourceLocation,
+        name: String,
+        suggestion: Suggestion
+    },
     #[error("Unknown offset: {0}")]
     UnknownOffset(String),
     #[error("Invalid offset: {0}")]
     InvalidOffset(String),
-    #[error("Missing definition value: {0}")]
-    MissingDefinitionValue(String)
+    #[error("{location}: Missing definition value: {name}")]
+    MissingDefinitionValue {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Definition {name} is defined twice, first at {first}")]
+    DuplicateDefinition {
+        location: SourceLocation,
+        name: String,
+        first: SourceLocation
+    },
+    #[error("{location}: {name} is defined with type {kind} at {defined} and cannot be used as {usage}")]
+    DefinitionMismatch {
+        location: SourceLocation,
+        name: String,
+        kind: DefinitionKind,
+        usage: &'static str,
+        defined: SourceLocation
+    },
+    #[error("{location}: {name} = {value} (defined at {defined}) does not fit in {usage}")]
+    DefinitionOutOfRange {
+        location: SourceLocation,
+        name: String,
+        value: i16,
+        usage: &'static str,
+        defined: SourceLocation
+    },
+    #[error("{location}: Address expression {expression} = {value} is out of range (0x0000 to 0x07FE)")]
+    AddressOutOfRange {
+        location: SourceLocation,
+        expression: String,
+        value: i32
+    },
+    #[error("{location}: Immediate expression {expression} = {value} is out of range (-128 to 255)")]
+    ImmediateOutOfRange {
+        location: SourceLocation,
+        expression: String,
+        value: i32
+    },
+    #[error("{location}: Could not evaluate {expression}")]
+    InvalidExpression {
+        location: SourceLocation,
+        expression: String
+    },
+    #[error("{location}: Assertion {expression} failed: {message}")]
+    AssertionFailed {
+        location: SourceLocation,
+        expression: String,
+        message: String
+    },
+    #[error("{location}: {message}")]
+    UserError {
+        location: SourceLocation,
+        message: String
+    }
 }
 
 pub fn evaluate_program(program: &mut [Instruction]) -> Result<(), EvaluatorError>{
     info!("Evaluating program...");
-    let defined: Definitions = find_definitions(&program)?;
+    let mut defined: Definitions = find_definitions(program)?;
+    import_definitions(program, &mut defined);
 
-    for i in 0..program.len() {
-        evaluate_instruction(&mut program[i], &defined)?;
+    for instruction in program.iter_mut() {
+        evaluate_instruction(instruction, &defined)?;
+        evaluate_expressions(instruction)?;
+        check_diagnostics(instruction)?;
     }
 
     Ok(())
@@ -32,16 +116,32 @@ pub fn evaluate_program(program: &mut [Instruction]) -> Result<(), EvaluatorErro
 fn find_definitions(program: &[Instruction]) -> Result<Definitions, EvaluatorError> {
     let mut defined: Definitions = HashMap::new();
     for instruction in program.iter() {
-        if let Opcode::_Definition = instruction.opcode {
-            if let Operand::Def(def) = &instruction.operands[0] {
+        if instruction.opcode.is_definition() {
+            if let Some(Operand::Def(def)) = instruction.operands.first() {
                 let imm = def.value;
 
                 if imm.is_none() {
                     // This should never happen, as the parser should catch this
-                    return Err(EvaluatorError::MissingDefinitionValue(def.name.clone()));
+                    return Err(EvaluatorError::MissingDefinitionValue {
+                        location: instruction.source.clone(),
+                        name: def.name.clone()
+                    });
+                }
+
+                let key = (instruction.source.file.clone(), def.name.clone());
+                if let Some(first) = defined.get(&key) {
+                    return Err(EvaluatorError::DuplicateDefinition {
+                        location: instruction.source.clone(),
+                        name: def.name.clone(),
+                        first: first.source.clone()
+                    });
                 }
 
-                defined.insert(def.name.clone(), imm.unwrap());
+                defined.insert(key, Defined {
+                    value: imm.unwrap(),
+                    kind: def.kind,
+                    source: instruction.source.clone()
+                });
                 trace!("Found definition: {} = {}", def.name, imm.unwrap());
             }
         }
@@ -50,21 +150,197 @@ fn find_definitions(program: &[Instruction]) -> Result<Definitions, EvaluatorErr
     Ok(defined)
 }
 
+/// Makes definitions exported with `.global` visible in the files that `.extern` them.
+/// The resolver already checked that every import has exactly one export
+fn import_definitions(program: &[Instruction], defined: &mut Definitions) {
+    let exported: HashMap<String, Defined> = declared_names(program, Opcode::_Global)
+        .into_keys()
+        .filter_map(|key| defined.get(&key).map(|d| (key.1, d.clone())))
+        .collect();
+
+    for (file, name) in declared_names(program, Opcode::_Extern).into_keys() {
+        if let Some(d) = exported.get(&name) {
+            trace!("{} imports definition {} = {}", file, name, d.value);
+            defined.insert((file, name), d.clone());
+        }
+    }
+}
+
 fn evaluate_instruction(instruction: &mut Instruction, defined: &Definitions) -> Result<(), EvaluatorError> {
-    for i in 0..instruction.operands.len() {
-        let operand = &mut instruction.operands[i];
-        if Opcode::_Definition != instruction.opcode {
-            if let Operand::Def(def) = operand {
-                match defined.get(&def.name) {
-                    Some(i) => {
-                        trace!("Replacing {} with {}", def.name, i);
-                        def.value = Some(*i);
-                    },
-                    None => return Err(EvaluatorError::UnknownDefinition(def.name.clone()))
+    if instruction.opcode.is_definition() {
+        return Ok(());
+    }
+
+    let file = instruction.source.file.clone();
+    let opcode = instruction.opcode;
+    for (operand, location) in instruction.located_operands_mut() {
+        let (definitions, usage) = match operand {
+            Operand::Def(def) if matches!(opcode, Opcode::LOD | Opcode::STR) => (vec![def], Usage::Offset),
+            Operand::Def(def) => (vec![def], Usage::Immediate),
+            Operand::Expr(expr) => (expr.definitions_mut(), Usage::Expression),
+            _ => continue
+        };
+
+        for def in definitions {
+            match defined.get(&(file.clone(), def.name.clone())) {
+                Some(d) => {
+                    check_usage(&def.name, d, usage, location)?;
+                    trace!("Replacing {} with {}", def.name, d.value);
+                    def.value = Some(d.value);
+                    def.kind = d.kind;
+                },
+                None => {
+                    // Misspelled port names end up here too, as they parse as definitions
+                    let visible = defined
+                        .keys()
+                        .filter(|(f, _)| *f == file)
+                        .map(|(_, name)| name.as_str())
+                        .chain(PORTS.iter().copied());
+
+                    return Err(EvaluatorError::UnknownDefinition {
+                        location: location.clone(),
+                        name: def.name.clone(),
+                        suggestion: Suggestion::closest(&def.name, visible)
+                    });
                 }
             }
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Checks that a typed definition is used as what it was defined as, and that its value fits
+fn check_usage(name: &str, defined: &Defined, usage: Usage, location: &SourceLocation) -> Result<(), EvaluatorError> {
+    if let Some(kind) = defined.kind {
+        if !usage.accepts(kind) {
+            return Err(EvaluatorError::DefinitionMismatch {
+                location: location.clone(),
+                name: name.to_string(),
+                kind,
+                usage: usage.name(),
+                defined: defined.source.clone()
+            });
+        }
+    }
+
+    if let Some(range) = usage.range() {
+        if !range.contains(&defined.value) {
+            return Err(EvaluatorError::DefinitionOutOfRange {
+                location: location.clone(),
+                name: name.to_string(),
+                value: defined.value,
+                usage: usage.name(),
+                defined: defined.source.clone()
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Computes expressions once their labels and definitions are bound, `$` being the instruction's own address
+fn evaluate_expressions(instruction: &mut Instruction) -> Result<(), EvaluatorError> {
+    // Table values are evaluated in the code that `.init_tables` generates for them
+    if instruction.opcode == Opcode::_Table {
+        return Ok(());
+    }
+
+    let here = instruction.location;
+    let fields = instruction.opcode.fields().unwrap_or_default();
+
+    for (i, (operand, location)) in instruction.located_operands_mut().enumerate() {
+        if let Operand::Expr(expr) = operand {
+            let is_address = fields.get(i) == Some(&Field::Address);
+            let is_immediate = fields.get(i) == Some(&Field::Immediate);
+
+            let value = match expr.evaluate(here) {
+                Some(v) => v,
+                None => {
+                    return Err(EvaluatorError::InvalidExpression {
+                        location: location.clone(),
+                        expression: expr.node.to_string()
+                    });
+                }
+            };
+
+            if is_address && u16::try_from(value).ok().and_then(Address::new).is_none() {
+                return Err(EvaluatorError::AddressOutOfRange {
+                    location: location.clone(),
+                    expression: expr.node.to_string(),
+                    value
+                });
+            }
+
+            if is_immediate && i16::try_from(value).ok().and_then(Immediate::new).is_none() {
+                return Err(EvaluatorError::ImmediateOutOfRange {
+                    location: location.clone(),
+                    expression: expr.node.to_string(),
+                    value
+                });
+            }
+            trace!("Evaluated {} to {}", expr.node, value);
+        }
+    }
+    Ok(())
+}
+
+/// Fails the build on `.error` or a false `.assert`, and reports `.warning`
+fn check_diagnostics(instruction: &Instruction) -> Result<(), EvaluatorError> {
+    let message = match instruction.operands.last() {
+        Some(Operand::Text(message)) => message.clone(),
+        _ => return Ok(())
+    };
+
+    match instruction.opcode {
+        Opcode::_Assert => {
+            if let Operand::Expr(expr) = &instruction.operands[0] {
+                if expr.value() == Some(0) {
+                    return Err(EvaluatorError::AssertionFailed {
+                        location: instruction.source.clone(),
+                        expression: expr.node.to_string(),
+                        message
+                    });
+                }
+            }
+            Ok(())
+        },
+        Opcode::_Error => {
+            Err(EvaluatorError::UserError {
+                location: instruction.source.clone(),
+                message
+            })
+        },
+        Opcode::_Warning => {
+            warn!("{}: {}", instruction.source, message);
+            Ok(())
+        },
+        _ => Ok(())
+    }
+}
+
+impl Usage {
+    fn accepts(&self, kind: DefinitionKind) -> bool {
+        match self {
+            Usage::Immediate => matches!(kind, DefinitionKind::Immediate | DefinitionKind::Port),
+            Usage::Offset => kind == DefinitionKind::Offset,
+            Usage::Expression => kind != DefinitionKind::Register
+        }
+    }
+
+    /// Expressions are only checked once they are computed
+    fn range(&self) -> Option<RangeInclusive<i16>> {
+        match self {
+            Usage::Immediate => Some(DefinitionKind::Immediate.range()),
+            Usage::Offset => Some(DefinitionKind::Offset.range()),
+            Usage::Expression => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Usage::Immediate => "an immediate (-128 to 255)",
+            Usage::Offset => "an offset (-8 to 7)",
+            Usage::Expression => "part of an expression"
+        }
+    }
+}
@@ -0,0 +1,1162 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use thiserror::Error;
+use crate::architecture::batpu2::charset::{Charset, DISPLAY_LENGTH};
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::operand::expression::{Expression, Node, Operator};
+use crate::architecture::batpu2::operand::immediate::{Immediate, Offset};
+use crate::architecture::batpu2::operand::label::Label;
+use crate::architecture::batpu2::operand::condition::Condition;
+use crate::architecture::batpu2::operand::Operand;
+use crate::architecture::batpu2::operand::port::Port;
+use crate::architecture::batpu2::operand::register::Register;
+use crate::source::SourceLocation;
+use crate::sprite::{Sprite, SpriteError, SCREEN_SIZE};
+
+/// Bytes that one load of the base register reaches with STR offsets
+const TABLE_WINDOW: usize = (Offset::MAX as i16 - Offset::MIN as i16 + 1) as usize;
+
+/// Where the shared multiply and divide routines take their operands. Multiply returns the product
+/// in the first register, divide returns the quotient in the first and the remainder in the second.
+/// Both overwrite r12 to r15. They go after the last instruction of the program, which has to stop
+/// or jump away so it does not run on into them
+const ARGUMENTS: (Register, Register) = (Register::R12, Register::R13);
+
+#[derive(Debug, Error)]
+pub enum ExpandError {
+    #[error("{location}: \"{text}\" is {length} characters long, the display only fits {DISPLAY_LENGTH}")]
+    StringTooLong {
+        location: SourceLocation,
+        text: String,
+        length: usize
+    },
+    #[error("{location}: Character '{character}' cannot be shown on the character display")]
+    UnsupportedCharacter {
+        location: SourceLocation,
+        character: char
+    },
+    #[error("{location}: Expected a register, found {found}")]
+    ExpectedRegister {
+        location: SourceLocation,
+        found: Operand
+    },
+    #[error("{location}: The stack is already declared at {first}, there is one per program")]
+    DuplicateStack {
+        location: SourceLocation,
+        first: SourceLocation
+    },
+    #[error("{location}: {opcode} needs a stack, declare one with .stack {{register}}, {{length}}")]
+    MissingStack {
+        location: SourceLocation,
+        opcode: Opcode
+    },
+    #[error("{location}: {error}")]
+    Sprite {
+        location: SourceLocation,
+        error: SpriteError
+    },
+    #[error("{location}: The {width}x{height} sprite does not fit on the screen at {axis} = {position}")]
+    SpriteOffScreen {
+        location: SourceLocation,
+        axis: char,
+        position: u8,
+        width: usize,
+        height: usize
+    },
+    #[error("{location}: Division by zero")]
+    DivisionByZero {
+        location: SourceLocation
+    },
+    #[error("{location}: {opcode} swaps its operands through the scratch register, which cannot be {scratch}")]
+    ScratchIsArgument {
+        location: SourceLocation,
+        opcode: Opcode,
+        scratch: Register
+    },
+    #[error("{location}: {opcode} without an open {opening}")]
+    UnopenedBlock {
+        location: SourceLocation,
+        opcode: Opcode,
+        opening: Opcode
+    },
+    #[error("{location}: {opcode} is never closed with {closing}")]
+    UnclosedBlock {
+        location: SourceLocation,
+        opcode: Opcode,
+        closing: Opcode
+    },
+}
+
+/// Code that `MUL`, `DIV` and `MOD` call when the second operand is not a known constant
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Routine {
+    Multiply,
+    Divide
+}
+
+/// An `.if` (or its `.else`), `.while` or `.loop` that is not closed yet
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    opcode: Opcode,
+    /// Numbers the labels of the block, e.g. `.endif@3`
+    id: usize,
+    /// The register that `.loop` counts down
+    counter: Option<Operand>,
+    /// The comparison of `.while` and the branch back to the top, which go at the bottom
+    test: Vec<Instruction>
+}
+
+/// Turns directives that generate code into real instructions. The directive itself is kept
+/// in the program (it takes no space) so listings show where the code came from
+pub struct Expander {
+    /// Register that expansions may overwrite, set with `.scratch`
+    scratch: Register,
+    /// Register that expansions use to address the I/O ports, set with `.port_base`
+    port_base: Register,
+    /// Every `.table` in the program, written to data memory by `.init_tables`
+    tables: Vec<Instruction>,
+    /// The stack pointer of the program's `.stack`
+    stack_pointer: Option<Register>,
+    /// Labels generated so far, which keeps their names unique
+    generated_labels: usize,
+    /// Values of the definitions in each file, so constant operands can be unrolled
+    constants: HashMap<(String, String), i16>,
+    /// The first call to each shared routine, they are emitted once at the end of the program
+    routines: Vec<(Routine, SourceLocation)>,
+    /// Open `.if`, `.while` and `.loop` blocks, innermost last
+    blocks: Vec<Block>,
+    /// Whether each `.if` that is still to come has an `.else`, in program order
+    else_branches: VecDeque<bool>
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Expander {
+    pub fn new() -> Expander {
+        Expander {
+            scratch: Register::R14,
+            port_base: Register::R15,
+            tables: Vec::new(),
+            stack_pointer: None,
+            generated_labels: 0,
+            constants: HashMap::new(),
+            routines: Vec::new(),
+            blocks: Vec::new(),
+            else_branches: VecDeque::new()
+        }
+    }
+
+    pub fn expand_program(&mut self, program: Vec<Instruction>) -> Result<Vec<Instruction>, ExpandError> {
+        info!("Expanding program...");
+        let mut expanded: Vec<Instruction> = Vec::with_capacity(program.len());
+        self.tables = program
+            .iter()
+            .filter(|i| i.opcode == Opcode::_Table)
+            .cloned()
+            .collect();
+
+        self.stack_pointer = find_stack(&program)?;
+        self.else_branches = match_blocks(&program)?;
+        self.constants = program
+            .iter()
+            .filter(|i| i.opcode == Opcode::_Definition)
+            .filter_map(|i| match i.operands.first() {
+                Some(Operand::Def(def)) => def.value.map(|v| ((i.source.file.clone(), def.name.clone()), v)),
+                _ => None
+            })
+            .collect();
+
+        if let Some(table) = self.tables.first() {
+            if !program.iter().any(|i| i.opcode == Opcode::_InitTables) {
+                warn!("{}: Tables are never written to data memory, add .init_tables where they should be", table.source);
+            }
+        }
+
+        for instruction in program {
+            let generated = self.expand_instruction(&instruction)?;
+            expanded.push(instruction);
+            expanded.extend(generated);
+        }
+
+        if let Some((routine, _)) = self.routines.first() {
+            let last = expanded.iter().rev().find(|i| !i.opcode.is_directive());
+            if let Some(last) = last.filter(|i| !matches!(i.opcode, Opcode::HLT | Opcode::JMP | Opcode::RET)) {
+                warn!("{}: The program runs on into {} after its last instruction, end it with HLT, JMP or RET", last.source, routine.label());
+            }
+        }
+        for (routine, source) in &self.routines {
+            expanded.extend(routine.emit().into_iter().map(|mut instruction| {
+                instruction.source = source.clone();
+                instruction
+            }));
+        }
+
+        debug!("Program expanded to {} instructions", expanded.len());
+        Ok(expanded)
+    }
+
+    fn expand_instruction(&mut self, instruction: &Instruction) -> Result<Vec<Instruction>, ExpandError> {
+        let source = &instruction.source;
+        let generated = match instruction.opcode {
+            Opcode::_Scratch => {
+                self.scratch = expect_register(&instruction.operands[0], source)?;
+                self.check_registers(source);
+                Vec::new()
+            },
+            Opcode::_PortBase => {
+                self.port_base = expect_register(&instruction.operands[0], source)?;
+                self.check_registers(source);
+                Vec::new()
+            },
+            Opcode::_Print => {
+                match &instruction.operands[0] {
+                    Operand::Text(text) => self.expand_print(text, source)?,
+                    _ => Vec::new()
+                }
+            },
+            Opcode::_PrintNumber => self.expand_print_number(&instruction.operands[0]),
+            Opcode::CMPI | Opcode::SUBI | Opcode::ANDI | Opcode::XORI => self.expand_immediate_alu(instruction),
+            Opcode::LDI16 | Opcode::ADD16 | Opcode::SUB16 | Opcode::INC16 | Opcode::CMP16 => self.expand_wide(instruction),
+            Opcode::MUL | Opcode::DIV | Opcode::MOD => self.expand_arithmetic(instruction)?,
+            Opcode::_Sprite => self.expand_sprite(instruction)?,
+            Opcode::_If | Opcode::_Else | Opcode::_EndIf => self.expand_if(instruction)?,
+            Opcode::_While | Opcode::_EndWhile | Opcode::_Loop | Opcode::_EndLoop => self.expand_loop(instruction)?,
+            Opcode::_Stack | Opcode::PUSH | Opcode::POP | Opcode::PUSHALL | Opcode::POPALL => {
+                // The stack is found before expanding, so a `.stack` after its first use is fine
+                let sp = match self.stack_pointer {
+                    Some(sp) => sp,
+                    None => {
+                        return Err(ExpandError::MissingStack {
+                            location: source.clone(),
+                            opcode: instruction.opcode
+                        });
+                    }
+                };
+                match instruction.opcode {
+                    Opcode::_Stack => self.expand_stack(instruction, sp),
+                    _ => self.expand_stack_operation(instruction, sp)
+                }
+            },
+            Opcode::_Fill => {
+                match (&instruction.operands[0], &instruction.operands[1]) {
+                    (Operand::Count(count), Operand::Instr(repeated)) => {
+                        // A repeated pseudo-instruction expands every time, like it would anywhere else
+                        let mut filled = Vec::new();
+                        for _ in 0..*count {
+                            let generated = self.expand_instruction(repeated)?;
+                            filled.push((**repeated).clone());
+                            filled.extend(generated);
+                        }
+                        filled
+                    },
+                    _ => Vec::new()
+                }
+            },
+            // Table values are looked up in the file of their table, so the generated code keeps those sources
+            Opcode::_InitTables => return Ok(self.expand_init_tables()),
+            _ => Vec::new()
+        };
+
+        Ok(generated
+            .into_iter()
+            .map(|mut generated| {
+                generated.source = source.clone();
+                generated
+            })
+            .collect())
+    }
+
+    fn check_registers(&self, source: &SourceLocation) {
+        if self.scratch == self.port_base {
+            warn!("{}: The scratch register and the port base register are both {}", source, self.scratch);
+        }
+    }
+
+    /// Clears the character buffer, writes every character and pushes the buffer to the display.
+    /// Spaces are written straight from r0 and repeated characters reuse the scratch register
+    fn expand_print(&self, text: &str, source: &SourceLocation) -> Result<Vec<Instruction>, ExpandError> {
+        let length = text.chars().count();
+        if length > DISPLAY_LENGTH {
+            return Err(ExpandError::StringTooLong {
+                location: source.clone(),
+                text: text.to_string(),
+                length
+            });
+        }
+
+        let mut generated = vec![
+            self.load(self.port_base, Operand::Port(Port::WriteChar)),
+            self.store_port(Register::R0, Port::ClearCharsBuffer)
+        ];
+
+        let mut loaded: Option<u8> = None;
+        for character in text.chars() {
+            let code = match Charset::Display.encode(character) {
+                Some(c) => c,
+                None => {
+                    return Err(ExpandError::UnsupportedCharacter {
+                        location: source.clone(),
+                        character
+                    });
+                }
+            };
+
+            if code == 0 {
+                generated.push(self.store_port(Register::R0, Port::WriteChar));
+                continue;
+            }
+
+            if loaded != Some(code) {
+                let imm = Immediate::new(code as i16).unwrap();
+                generated.push(self.load(self.scratch, Operand::Imm(imm)));
+                loaded = Some(code);
+            }
+            generated.push(self.store_port(self.scratch, Port::WriteChar));
+        }
+
+        generated.push(self.store_port(Register::R0, Port::BufferChars));
+        Ok(generated)
+    }
+
+    /// Loads the immediate into the scratch register and runs the real operation on it
+    fn expand_immediate_alu(&self, instruction: &Instruction) -> Vec<Instruction> {
+        let a = &instruction.operands[0];
+        if a.register() == Some(self.scratch) {
+            warn!("{}: {} reads {}, which is the scratch register and holds the immediate instead",
+                instruction.operand_source(0), instruction.opcode, a);
+        }
+
+        let opcode = match instruction.opcode {
+            Opcode::CMPI => Opcode::CMP,
+            Opcode::SUBI => Opcode::SUB,
+            Opcode::ANDI => Opcode::AND,
+            _ => Opcode::XOR
+        };
+        let mut operation = Instruction::new(opcode);
+        operation.add_operand(a.clone());
+        operation.add_register(self.scratch);
+        if opcode != Opcode::CMP {
+            operation.add_operand(instruction.operands.get(2).unwrap_or(a).clone());
+        }
+
+        vec![
+            self.load(self.scratch, instruction.operands[1].clone()),
+            operation
+        ]
+    }
+
+    /// Points the stack pointer just past the end of the stack, which grows down
+    fn expand_stack(&self, instruction: &Instruction, sp: Register) -> Vec<Instruction> {
+        let (stack, length) = match &instruction.operands[..] {
+            [Operand::Def(stack), Operand::Imm(length), ..] => (stack, length),
+            _ => return Vec::new()
+        };
+        if sp == self.scratch || sp == self.port_base {
+            warn!("{}: The stack pointer {} is also the scratch or port base register", instruction.source, sp);
+        }
+
+        let top = Expression::new(Node::Binary(
+            Box::new(Node::Def(stack.clone())),
+            Operator::Add,
+            Box::new(Node::Number(length.value() as i32))
+        ));
+        vec![self.load(sp, Operand::Expr(top))]
+    }
+
+    /// `PUSH` stores below the stack pointer before moving it down, `POP` loads before moving it up.
+    /// The register lists move the stack pointer once per 8 registers, as far as STR offsets reach,
+    /// and `POPALL` restores in the opposite order so it takes the same list as `PUSHALL`
+    fn expand_stack_operation(&self, instruction: &Instruction, sp: Register) -> Vec<Instruction> {
+        let registers: Vec<Operand> = match instruction.opcode {
+            Opcode::PUSHALL | Opcode::POPALL if instruction.operands.is_empty() => {
+                Register::ALL[1..]
+                    .iter()
+                    .filter(|r| **r != sp)
+                    .map(|r| Operand::Reg(*r))
+                    .collect()
+            },
+            _ => instruction.operands.clone()
+        };
+
+        for (i, register) in registers.iter().enumerate() {
+            if register.register() == Some(sp) {
+                warn!("{}: {} saves or restores the stack pointer {}", instruction.operand_source(i), instruction.opcode, sp);
+            }
+        }
+
+        let window = -Offset::MIN as usize;
+        let mut generated = Vec::new();
+        match instruction.opcode {
+            Opcode::PUSH | Opcode::PUSHALL => {
+                for group in registers.chunks(window) {
+                    for (i, register) in group.iter().enumerate() {
+                        generated.push(memory(Opcode::STR, sp, register.clone(), -(i as i16 + 1)));
+                    }
+                    generated.push(add_to(sp, -(group.len() as i16)));
+                }
+            },
+            _ => {
+                for group in registers.chunks(window).rev() {
+                    for (i, register) in group.iter().enumerate().rev() {
+                        generated.push(memory(Opcode::LOD, sp, register.clone(), (group.len() - 1 - i) as i16));
+                    }
+                    generated.push(add_to(sp, group.len() as i16));
+                }
+            }
+        }
+        generated
+    }
+
+    /// 16-bit operations on register pairs. The high bytes go first, so the carry (or borrow) of
+    /// the low bytes is still in the flags for the branch that skips correcting the high byte
+    fn expand_wide(&mut self, instruction: &Instruction) -> Vec<Instruction> {
+        let pairs: Vec<(Register, Register)> = instruction.operands
+            .iter()
+            .filter_map(|o| match o {
+                Operand::Pair(pair) => pair.registers,
+                _ => None
+            })
+            .collect();
+
+        if let (Opcode::LDI16, [(high, low)], Some(Operand::Expr(value))) = (instruction.opcode, &pairs[..], instruction.operands.get(1)) {
+            let byte = |node: Node| Operand::Expr(Expression::new(Node::Binary(
+                Box::new(node),
+                Operator::And,
+                Box::new(Node::Number(0xFF))
+            )));
+            let shifted = Node::Binary(Box::new(value.node.clone()), Operator::Shr, Box::new(Node::Number(8)));
+            return vec![
+                self.load(*high, byte(shifted)),
+                self.load(*low, byte(value.node.clone()))
+            ];
+        }
+
+        let skip = self.generate_label(instruction.opcode);
+        let mut generated = match (instruction.opcode, &pairs[..]) {
+            (Opcode::ADD16 | Opcode::SUB16, [a, b, rest @ ..]) => {
+                let c = rest.first().unwrap_or(a);
+                let (opcode, branch, fix) = match instruction.opcode {
+                    Opcode::ADD16 => (Opcode::ADD, Condition::C0, Opcode::INC),
+                    _ => (Opcode::SUB, Condition::C1, Opcode::DEC)
+                };
+                vec![
+                    three(opcode, a.0, b.0, c.0),
+                    three(opcode, a.1, b.1, c.1),
+                    branch_if(branch, &skip),
+                    one(fix, c.0)
+                ]
+            },
+            (Opcode::CMP16, [a, b]) => {
+                vec![
+                    two(Opcode::CMP, a.0, b.0),
+                    branch_if(Condition::Z0, &skip),
+                    two(Opcode::CMP, a.1, b.1)
+                ]
+            },
+            (Opcode::INC16, [(high, low)]) => {
+                vec![
+                    one(Opcode::INC, *low),
+                    branch_if(Condition::Z0, &skip),
+                    one(Opcode::INC, *high)
+                ]
+            },
+            _ => return Vec::new()
+        };
+
+        generated.push(label(skip));
+        generated
+    }
+
+    /// Multiplication by a constant is unrolled into shifts and adds, division and modulo by a power
+    /// of two into shifts or a mask. Everything else copies the operands into [`ARGUMENTS`] and
+    /// calls the shared routine, so those registers do not survive it. Operands that are in each
+    /// other's argument register are swapped through the scratch register, which is overwritten too
+    fn expand_arithmetic(&mut self, instruction: &Instruction) -> Result<Vec<Instruction>, ExpandError> {
+        let opcode = instruction.opcode;
+        let a = expect_register_operand(&instruction.operands[0], instruction.operand_source(0))?;
+        let c = match instruction.operands.get(2) {
+            Some(c) => expect_register_operand(c, instruction.operand_source(2))?,
+            None => a.clone()
+        };
+        let b = &instruction.operands[1];
+
+        if let Some(k) = self.constant(b, &instruction.source.file) {
+            if k == 0 && opcode != Opcode::MUL {
+                return Err(ExpandError::DivisionByZero {
+                    location: instruction.operand_source(1).clone()
+                });
+            }
+            if let Some(unrolled) = self.unroll(instruction, &a, k, &c) {
+                return Ok(unrolled);
+            }
+        }
+
+        let (first, second) = ARGUMENTS;
+        let mut generated = match register_operand(b) {
+            // Copying a first would overwrite b, and the other order would overwrite a
+            Some(b) if b.register() == Some(first) && a.register() == Some(second) => {
+                if self.scratch == first || self.scratch == second {
+                    return Err(ExpandError::ScratchIsArgument {
+                        location: instruction.source.clone(),
+                        opcode,
+                        scratch: self.scratch
+                    });
+                }
+                vec![
+                    two(Opcode::MOV, a, self.scratch),
+                    two(Opcode::MOV, b, second),
+                    two(Opcode::MOV, self.scratch, first)
+                ]
+            },
+            Some(b) if b.register() == Some(first) => vec![two(Opcode::MOV, b, second), two(Opcode::MOV, a, first)],
+            Some(b) => vec![two(Opcode::MOV, a, first), two(Opcode::MOV, b, second)],
+            None => vec![two(Opcode::MOV, a, first), self.load(second, b.clone())]
+        };
+        generated.retain(|i| i.operands[0] != i.operands[1]);
+
+        let routine = match opcode {
+            Opcode::MUL => Routine::Multiply,
+            _ => Routine::Divide
+        };
+        let shared = match self.routines.iter().find(|(r, _)| *r == routine) {
+            Some((_, source)) => source.file.clone(),
+            None => {
+                self.routines.push((routine, instruction.source.clone()));
+                instruction.source.file.clone()
+            }
+        };
+
+        generated.push(branch_to(Opcode::CAL, routine.label()));
+        let result = if opcode == Opcode::MOD { second } else { first };
+        if c.register() != Some(result) {
+            generated.push(two(Opcode::MOV, result, c));
+        }
+        // Labels belong to their file, so other files import the routine
+        if shared != instruction.source.file {
+            let mut import = Instruction::new(Opcode::_Extern);
+            import.add_label_name(routine.label().to_string());
+            generated.push(import);
+        }
+        Ok(generated)
+    }
+
+    /// The value of a number or a definition in `file`, as the byte it is loaded as
+    fn constant(&self, operand: &Operand, file: &str) -> Option<u8> {
+        match operand {
+            Operand::Imm(imm) => Some(imm.value()),
+            Operand::Def(def) => {
+                let value = self.constants.get(&(file.to_string(), def.name.clone()))?;
+                Immediate::new(*value).map(|imm| imm.value())
+            },
+            _ => None
+        }
+    }
+
+    /// `MUL` by any constant, `DIV` and `MOD` by a power of two
+    fn unroll(&self, instruction: &Instruction, a: &Operand, k: u8, c: &Operand) -> Option<Vec<Instruction>> {
+        let shift = k.trailing_zeros();
+        let scratch = Operand::Reg(self.scratch);
+        let copy = || if a == c { Vec::new() } else { vec![two(Opcode::MOV, a.clone(), c.clone())] };
+        let shifts = |opcode: Opcode| match shift {
+            0 => copy(),
+            _ => {
+                let mut generated = vec![two(opcode, a.clone(), c.clone())];
+                generated.extend(std::iter::repeat_n(two(opcode, c.clone(), c.clone()), shift as usize - 1));
+                generated
+            }
+        };
+
+        let generated = match instruction.opcode {
+            Opcode::MUL if k == 0 => vec![self.load(c.clone(), Operand::Imm(Immediate::new(0).unwrap()))],
+            Opcode::MUL if k.is_power_of_two() => shifts(Opcode::LSH),
+            Opcode::MUL => {
+                if *a == scratch || *c == scratch {
+                    warn!("{}: {} uses {}, which is the scratch register and holds the shifted operand instead",
+                        instruction.source, instruction.opcode, self.scratch);
+                }
+                // The scratch register holds a shifted left once per bit, c adds it for every set bit
+                let mut generated = vec![two(Opcode::MOV, a.clone(), self.scratch)];
+                let top = 7 - k.leading_zeros();
+                for bit in 0..=top {
+                    if k & (1 << bit) != 0 {
+                        generated.push(if bit == shift {
+                            two(Opcode::MOV, self.scratch, c.clone())
+                        } else {
+                            three(Opcode::ADD, c.clone(), self.scratch, c.clone())
+                        });
+                    }
+                    if bit != top {
+                        generated.push(two(Opcode::LSH, self.scratch, self.scratch));
+                    }
+                }
+                generated
+            },
+            Opcode::DIV if k.is_power_of_two() => shifts(Opcode::RSH),
+            Opcode::MOD if k == 1 => vec![self.load(c.clone(), Operand::Imm(Immediate::new(0).unwrap()))],
+            Opcode::MOD if k.is_power_of_two() => {
+                if *a == scratch {
+                    warn!("{}: {} reads {}, which is the scratch register and holds the mask instead",
+                        instruction.operand_source(0), instruction.opcode, a);
+                }
+                vec![
+                    self.load(self.scratch, Operand::Imm(Immediate::new(k as i16 - 1).unwrap())),
+                    three(Opcode::AND, a.clone(), self.scratch, c.clone())
+                ]
+            },
+            _ => return None
+        };
+        Some(generated)
+    }
+
+    /// Draws the set pixels of a sprite, storing a coordinate only when it changes. The pixels go
+    /// row by row or column by column, whichever takes fewer instructions. Coordinates given as
+    /// registers are stepped through the sprite and put back afterwards, numbers go through the
+    /// scratch register
+    fn expand_sprite(&self, instruction: &Instruction) -> Result<Vec<Instruction>, ExpandError> {
+        let path = match &instruction.operands[0] {
+            Operand::Text(path) => path,
+            _ => return Ok(Vec::new())
+        };
+        // Sprites are found next to the file that draws them
+        let file = Path::new(&instruction.source.file).parent().unwrap_or(Path::new("")).join(path);
+        let sprite = Sprite::load(&file).map_err(|error| ExpandError::Sprite {
+            location: instruction.operand_source(0).clone(),
+            error
+        })?;
+
+        let mut axes = [(self.scratch, 0), (self.scratch, 0)];
+        let mut origins: HashMap<Register, i16> = HashMap::new();
+        for (i, size) in [sprite.width, sprite.height].into_iter().enumerate() {
+            match instruction.operands.get(i + 1) {
+                Some(Operand::Imm(position)) => {
+                    if position.value() as usize + size > SCREEN_SIZE {
+                        return Err(ExpandError::SpriteOffScreen {
+                            location: instruction.operand_source(i + 1).clone(),
+                            axis: ['x', 'y'][i],
+                            position: position.value(),
+                            width: sprite.width,
+                            height: sprite.height
+                        });
+                    }
+                    axes[i].1 = position.value() as i16;
+                },
+                Some(operand) => {
+                    let register = expect_register(operand, instruction.operand_source(i + 1))?;
+                    if register == self.scratch || register == self.port_base {
+                        warn!("{}: The sprite position {} is also the scratch or port base register",
+                            instruction.operand_source(i + 1), register);
+                    }
+                    axes[i].0 = register;
+                    origins.insert(register, 0);
+                },
+                None => {}
+            }
+        }
+
+        let rows: Vec<(usize, usize)> = sprite.set_pixels().collect();
+        let mut columns = rows.clone();
+        columns.sort_by_key(|(x, y)| (*x, *y));
+
+        let by_rows = self.draw_pixels(&rows, axes, origins.clone());
+        let by_columns = self.draw_pixels(&columns, axes, origins);
+        Ok(if by_columns.len() < by_rows.len() { by_columns } else { by_rows })
+    }
+
+    /// `axes` holds the register and the value of coordinate 0 for x and y, the bottom left corner
+    /// of the sprite. `values` tracks what the registers hold, relative to their value before the
+    /// sprite for the position registers
+    fn draw_pixels(&self, pixels: &[(usize, usize)], axes: [(Register, i16); 2], mut values: HashMap<Register, i16>) -> Vec<Instruction> {
+        // Restored in register order, a map has no order of its own
+        let mut positions: Vec<Register> = values.keys().copied().collect();
+        positions.sort_by_key(|register| *register as u16);
+        let mut generated = vec![self.load(self.port_base, Operand::Port(Port::PixelX))];
+        let mut latched: [Option<usize>; 2] = [None, None];
+
+        for (x, y) in pixels {
+            for (i, coordinate) in [*x, *y].into_iter().enumerate() {
+                if latched[i] == Some(coordinate) {
+                    continue;
+                }
+                let (register, start) = axes[i];
+                let target = start + coordinate as i16;
+                match values.get(&register).copied() {
+                    Some(current) if current == target => {},
+                    Some(current) => generated.push(add_to(register, (target - current + 128).rem_euclid(256) - 128)),
+                    None => generated.push(self.load(register, Operand::Imm(Immediate::new(target).unwrap())))
+                }
+                values.insert(register, target);
+                generated.push(self.store(self.port_base, register, i as i16));
+                latched[i] = Some(coordinate);
+            }
+            generated.push(self.store(self.port_base, Register::R0, Port::DrawPixel as i16 - Port::PixelX as i16));
+        }
+
+        for register in positions {
+            match values[&register] {
+                0 => {},
+                moved => generated.push(add_to(register, -moved))
+            }
+        }
+        generated
+    }
+
+    /// Branches past the code of an `.if` when its condition is false, to its `.else` if it has
+    /// one. The code before the `.else` ends by jumping over the code after it
+    fn expand_if(&mut self, instruction: &Instruction) -> Result<Vec<Instruction>, ExpandError> {
+        match instruction.opcode {
+            Opcode::_If => {
+                let (mut generated, condition) = self.compare(instruction)?;
+                let id = self.open_block(Opcode::_If, None);
+                let skip = match self.else_branches.pop_front() {
+                    Some(true) => Opcode::_Else,
+                    _ => Opcode::_EndIf
+                };
+                generated.push(branch_if(condition.negate(), &block_label(skip, id)));
+                Ok(generated)
+            },
+            Opcode::_Else => {
+                Ok(self.blocks.last().map_or_else(Vec::new, |block| vec![
+                    branch_to(Opcode::JMP, &block_label(Opcode::_EndIf, block.id)),
+                    label(block_label(Opcode::_Else, block.id))
+                ]))
+            },
+            _ => Ok(self.blocks.pop().map_or_else(Vec::new, |block| vec![
+                label(block_label(Opcode::_EndIf, block.id))
+            ]))
+        }
+    }
+
+    /// `.while` jumps to its condition at the bottom, which branches back up while it holds, so
+    /// each round only takes one branch. The body of `.loop` runs until `.endloop` counts the
+    /// register down to zero
+    fn expand_loop(&mut self, instruction: &Instruction) -> Result<Vec<Instruction>, ExpandError> {
+        match instruction.opcode {
+            Opcode::_While => {
+                let (mut test, condition) = self.compare(instruction)?;
+                let id = self.open_block(Opcode::_While, None);
+                test.push(branch_if(condition, &block_label(Opcode::_While, id)));
+                self.blocks.last_mut().unwrap().test = test;
+                Ok(vec![
+                    branch_to(Opcode::JMP, &block_label(Opcode::_EndWhile, id)),
+                    label(block_label(Opcode::_While, id))
+                ])
+            },
+            Opcode::_Loop => {
+                let counter = expect_register_operand(&instruction.operands[0], instruction.operand_source(0))?;
+                let id = self.open_block(Opcode::_Loop, Some(counter));
+                Ok(vec![label(block_label(Opcode::_Loop, id))])
+            },
+            _ => {
+                let generated = match self.blocks.pop() {
+                    Some(Block { opcode: Opcode::_While, id, test, .. }) => {
+                        let mut generated = vec![label(block_label(Opcode::_EndWhile, id))];
+                        generated.extend(test);
+                        generated
+                    },
+                    Some(Block { counter: Some(counter), id, .. }) => vec![
+                        one(Opcode::DEC, counter),
+                        branch_if(Condition::Z0, &block_label(Opcode::_Loop, id))
+                    ],
+                    _ => Vec::new()
+                };
+                Ok(generated)
+            }
+        }
+    }
+
+    /// The `CMP` for the condition of `.if` or `.while`, along with the condition that it leaves in
+    /// the flags when the comparison is true. Constants other than zero go through the scratch register
+    fn compare(&self, instruction: &Instruction) -> Result<(Vec<Instruction>, Condition), ExpandError> {
+        let (a, comparison, b) = match &instruction.operands[..] {
+            [a, Operand::Compare(comparison), b] => (a, comparison, b),
+            [Operand::Cond(condition)] => return Ok((Vec::new(), *condition)),
+            _ => return Ok((Vec::new(), Condition::Z1))
+        };
+        let a = expect_register_operand(a, instruction.operand_source(0))?;
+        let mut generated = Vec::new();
+        let b = match (register_operand(b), self.constant(b, &instruction.source.file)) {
+            (Some(b), _) => b,
+            (None, Some(0)) => Operand::Reg(Register::R0),
+            _ => {
+                if a.register() == Some(self.scratch) {
+                    warn!("{}: {} compares {}, which is the scratch register and holds {} instead",
+                        instruction.operand_source(0), instruction.opcode, a, b);
+                }
+                generated.push(self.load(self.scratch, b.clone()));
+                Operand::Reg(self.scratch)
+            }
+        };
+
+        let (condition, swapped) = comparison.condition();
+        generated.push(match swapped {
+            true => two(Opcode::CMP, b, a),
+            false => two(Opcode::CMP, a, b)
+        });
+        Ok((generated, condition))
+    }
+
+    fn open_block(&mut self, opcode: Opcode, counter: Option<Operand>) -> usize {
+        self.generated_labels += 1;
+        self.blocks.push(Block {
+            opcode,
+            id: self.generated_labels,
+            counter,
+            test: Vec::new()
+        });
+        self.generated_labels
+    }
+
+    /// A local label no source can define, so it never clashes with one and is not scoped
+    fn generate_label(&mut self, opcode: Opcode) -> String {
+        self.generated_labels += 1;
+        format!(".{}@{}", opcode, self.generated_labels)
+    }
+
+    fn expand_print_number(&self, register: &Operand) -> Vec<Instruction> {
+        let mut store = Instruction::new(Opcode::STR);
+        store.add_register(self.port_base);
+        store.add_operand(register.clone());
+
+        vec![
+            self.load(self.port_base, Operand::Port(Port::ShowNumber)),
+            store
+        ]
+    }
+
+    /// Writes every table to data memory. The port base register points into the table, and as
+    /// STR offsets go from -8 to 7 it is loaded once per 16 bytes. Within those, every distinct
+    /// value is loaded into the scratch register once and zeros are stored straight from r0
+    fn expand_init_tables(&self) -> Vec<Instruction> {
+        let mut generated = Vec::new();
+        let mut loaded: Option<&Operand> = None;
+
+        for table in &self.tables {
+            let def = match &table.operands[0] {
+                Operand::Def(def) => def,
+                _ => continue
+            };
+
+            for (window, values) in table.operands[1..].chunks(TABLE_WINDOW).enumerate() {
+                let start = (window * TABLE_WINDOW) as i32;
+                // Point into the middle when the negative offsets are needed
+                let middle = if values.len() > Offset::MAX as usize + 1 { -Offset::MIN as i32 } else { 0 };
+                let address = match start + middle {
+                    0 => Operand::Def(def.clone()),
+                    n => Operand::Expr(Expression::new(Node::Binary(
+                        Box::new(Node::Def(def.clone())),
+                        Operator::Add,
+                        Box::new(Node::Number(n))
+                    )))
+                };
+                let mut code = vec![self.load(self.port_base, address)];
+
+                let mut distinct: Vec<&Operand> = Vec::new();
+                for value in values {
+                    if !distinct.contains(&value) {
+                        distinct.push(value);
+                    }
+                }
+                // Start with the value that is still in the scratch register
+                if let Some(i) = distinct.iter().position(|v| Some(*v) == loaded) {
+                    let value = distinct.remove(i);
+                    distinct.insert(0, value);
+                }
+
+                for value in distinct {
+                    let register = if matches!(value, Operand::Imm(imm) if imm.value() == 0) {
+                        Register::R0
+                    } else {
+                        if loaded != Some(value) {
+                            code.push(self.load(self.scratch, value.clone()));
+                            loaded = Some(value);
+                        }
+                        self.scratch
+                    };
+
+                    for (i, _) in values.iter().enumerate().filter(|(_, v)| *v == value) {
+                        code.push(self.store(self.port_base, register, i as i16 - middle as i16));
+                    }
+                }
+
+                generated.extend(code.into_iter().map(|mut instruction| {
+                    instruction.source = table.source.clone();
+                    instruction
+                }));
+            }
+        }
+        generated
+    }
+
+    fn load(&self, register: impl Into<Operand>, value: Operand) -> Instruction {
+        let mut temp = one(Opcode::LDI, register);
+        temp.add_operand(value);
+        temp
+    }
+
+    /// Stores to a character port relative to the port base, which holds WRITE_CHAR
+    fn store_port(&self, register: Register, port: Port) -> Instruction {
+        self.store(self.port_base, register, port as i16 - Port::WriteChar as i16)
+    }
+
+    fn store(&self, base: Register, register: Register, offset: i16) -> Instruction {
+        memory(Opcode::STR, base, Operand::Reg(register), offset)
+    }
+}
+
+impl Routine {
+    fn label(&self) -> &'static str {
+        match self {
+            Routine::Multiply => "multiply@routine",
+            Routine::Divide => "divide@routine"
+        }
+    }
+
+    /// Multiplies r12 by r13 with shift and add. Divides r12 by r13 one bit at a time, shifting the
+    /// dividend out of r12 and the quotient in. Dividing by zero gives 255, remainder r12
+    fn emit(&self) -> Vec<Instruction> {
+        use Register::{R0, R12, R13, R14, R15};
+        let mut routine = vec![Instruction::new(Opcode::_Global), Instruction::new(Opcode::_Label)];
+        routine[0].add_label_name(self.label().to_string());
+        routine[1].add_label_name(self.label().to_string());
+        let label = |name: &str| label(name.to_string());
+        let load = |register: Register, value: i16| {
+            let mut temp = one(Opcode::LDI, register);
+            temp.add_immediate(Immediate::new(value).unwrap());
+            temp
+        };
+
+        routine.extend(match self {
+            Routine::Multiply => vec![
+                two(Opcode::MOV, R12, R15),
+                load(R12, 0),
+                label(".loop"),
+                load(R14, 1),
+                three(Opcode::AND, R13, R14, R14),
+                branch_if(Condition::Z1, ".skip"),
+                three(Opcode::ADD, R12, R15, R12),
+                label(".skip"),
+                two(Opcode::LSH, R15, R15),
+                two(Opcode::RSH, R13, R13),
+                two(Opcode::CMP, R13, R0),
+                branch_if(Condition::Z0, ".loop"),
+                Instruction::new(Opcode::RET)
+            ],
+            // The remainder in r15 can need 9 bits after shifting, the carry says so and then it is
+            // certainly not smaller than the divisor
+            Routine::Divide => vec![
+                load(R15, 0),
+                load(R14, 8),
+                label(".loop"),
+                two(Opcode::LSH, R15, R15),
+                branch_if(Condition::C1, ".overflow"),
+                two(Opcode::LSH, R12, R12),
+                branch_if(Condition::C0, ".compare"),
+                one(Opcode::INC, R15),
+                label(".compare"),
+                two(Opcode::CMP, R15, R13),
+                branch_if(Condition::C0, ".next"),
+                label(".subtract"),
+                three(Opcode::SUB, R15, R13, R15),
+                one(Opcode::INC, R12),
+                label(".next"),
+                one(Opcode::DEC, R14),
+                branch_if(Condition::Z0, ".loop"),
+                two(Opcode::MOV, R15, R13),
+                Instruction::new(Opcode::RET),
+                label(".overflow"),
+                two(Opcode::LSH, R12, R12),
+                branch_if(Condition::C0, ".subtract"),
+                one(Opcode::INC, R15),
+                branch_to(Opcode::JMP, ".subtract")
+            ]
+        });
+        routine
+    }
+}
+
+/// Checks that every `.if`, `.while` and `.loop` is closed in the file it is in, and finds out
+/// which of the `.if`s have an `.else`
+fn match_blocks(program: &[Instruction]) -> Result<VecDeque<bool>, ExpandError> {
+    let closing = |opcode: Opcode| match opcode {
+        Opcode::_While => Opcode::_EndWhile,
+        Opcode::_Loop => Opcode::_EndLoop,
+        _ => Opcode::_EndIf
+    };
+    let unclosed = |block: &Instruction| ExpandError::UnclosedBlock {
+        location: block.source.clone(),
+        opcode: block.opcode,
+        closing: closing(block.opcode)
+    };
+
+    let mut else_branches: VecDeque<bool> = VecDeque::new();
+    // Open blocks with the index of their `.if` in `else_branches`
+    let mut open: Vec<(&Instruction, usize)> = Vec::new();
+    for instruction in program {
+        let opening = match instruction.opcode {
+            Opcode::_If | Opcode::_While | Opcode::_Loop => {
+                if instruction.opcode == Opcode::_If {
+                    else_branches.push_back(false);
+                }
+                open.push((instruction, else_branches.len().saturating_sub(1)));
+                continue;
+            },
+            Opcode::_Else | Opcode::_EndIf => Opcode::_If,
+            Opcode::_EndWhile => Opcode::_While,
+            Opcode::_EndLoop => Opcode::_Loop,
+            _ => continue
+        };
+
+        let (block, index) = match open.pop() {
+            Some(block) => block,
+            None => {
+                return Err(ExpandError::UnopenedBlock {
+                    location: instruction.source.clone(),
+                    opcode: instruction.opcode,
+                    opening
+                });
+            }
+        };
+        // An `.else` takes the place of its `.if`, so a second one does not close anything
+        let closes = match instruction.opcode {
+            Opcode::_Else => block.opcode == Opcode::_If,
+            Opcode::_EndIf => matches!(block.opcode, Opcode::_If | Opcode::_Else),
+            _ => block.opcode == opening
+        };
+        if !closes || block.source.file != instruction.source.file {
+            return Err(unclosed(block));
+        }
+        if instruction.opcode == Opcode::_Else {
+            else_branches[index] = true;
+            open.push((instruction, index));
+        }
+    }
+
+    match open.pop() {
+        Some((block, _)) => Err(unclosed(block)),
+        None => Ok(else_branches)
+    }
+}
+
+/// The stack pointer of the one `.stack` in the program, if there is one
+fn find_stack(program: &[Instruction]) -> Result<Option<Register>, ExpandError> {
+    let mut found: Option<(&Instruction, Register)> = None;
+    for instruction in program.iter().filter(|i| i.opcode == Opcode::_Stack) {
+        if let Some((first, _)) = found {
+            return Err(ExpandError::DuplicateStack {
+                location: instruction.source.clone(),
+                first: first.source.clone()
+            });
+        }
+        let sp = expect_register(&instruction.operands[2], instruction.operand_source(2))?;
+        found = Some((instruction, sp));
+    }
+    Ok(found.map(|(_, sp)| sp))
+}
+
+/// A `LOD` or `STR` relative to `base`
+fn memory(opcode: Opcode, base: Register, register: Operand, offset: i16) -> Instruction {
+    let mut temp = Instruction::new(opcode);
+    temp.add_register(base);
+    temp.add_operand(register);
+    if offset != 0 {
+        temp.add_offset(Offset::new(offset as i8).unwrap());
+    }
+    temp
+}
+
+/// Instructions on registers, which are a [`Register`] or a virtual register
+fn one(opcode: Opcode, a: impl Into<Operand>) -> Instruction {
+    let mut temp = Instruction::new(opcode);
+    temp.add_operand(a.into());
+    temp
+}
+
+fn two(opcode: Opcode, a: impl Into<Operand>, b: impl Into<Operand>) -> Instruction {
+    let mut temp = one(opcode, a);
+    temp.add_operand(b.into());
+    temp
+}
+
+fn three(opcode: Opcode, a: impl Into<Operand>, b: impl Into<Operand>, c: impl Into<Operand>) -> Instruction {
+    let mut temp = two(opcode, a, b);
+    temp.add_operand(c.into());
+    temp
+}
+
+fn label(name: String) -> Instruction {
+    let mut temp = Instruction::new(Opcode::_Label);
+    temp.add_label_name(name);
+    temp
+}
+
+/// The labels of `.if`, `.while` and `.loop` blocks are named after the directive they are at
+fn block_label(opcode: Opcode, id: usize) -> String {
+    format!("{}@{}", opcode, id)
+}
+
+fn branch_to(opcode: Opcode, label: &str) -> Instruction {
+    let mut temp = Instruction::new(opcode);
+    temp.add_label(Label::new(label.to_string()));
+    temp
+}
+
+/// `BRH` on `condition`, which the listing shows as the mnemonic for it
+fn branch_if(condition: Condition, label: &str) -> Instruction {
+    let mut temp = Instruction::new(Opcode::BRH);
+    temp.add_condition(condition);
+    temp.add_label(Label::new(label.to_string()));
+    temp.mnemonic = Some(condition.mnemonic().to_string());
+    temp
+}
+
+/// Adds to a register with `INC` or `DEC` where possible
+fn add_to(register: Register, by: i16) -> Instruction {
+    let mut temp = match by {
+        1 => Instruction::new(Opcode::INC),
+        -1 => Instruction::new(Opcode::DEC),
+        _ => Instruction::new(Opcode::ADI)
+    };
+    temp.add_register(register);
+    if by.abs() != 1 {
+        temp.add_immediate(Immediate::new(by).unwrap());
+    }
+    temp
+}
+
+/// A register, or a virtual register that only gets its register after expanding. Aliases
+/// are replaced by their register
+fn register_operand(operand: &Operand) -> Option<Operand> {
+    match operand {
+        Operand::Alias(alias) if alias.is_virtual() => Some(operand.clone()),
+        _ => operand.register().map(Operand::Reg)
+    }
+}
+
+fn expect_register_operand(operand: &Operand, source: &SourceLocation) -> Result<Operand, ExpandError> {
+    match register_operand(operand) {
+        Some(register) => Ok(register),
+        None => {
+            Err(ExpandError::ExpectedRegister {
+                location: source.clone(),
+                found: operand.clone()
+            })
+        }
+    }
+}
+
+fn expect_register(operand: &Operand, source: &SourceLocation) -> Result<Register, ExpandError> {
+    match operand.register() {
+        Some(r) => Ok(r),
+        None => {
+            Err(ExpandError::ExpectedRegister {
+                location: source.clone(),
+                found: operand.clone()
+            })
+        }
+    }
+}
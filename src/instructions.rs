@@ -0,0 +1,385 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use nom::error::convert_error;
+use thiserror::Error;
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::{Access, Field, Opcode};
+use crate::architecture::batpu2::operand::condition::Condition;
+use crate::architecture::batpu2::operand::immediate::Offset;
+use crate::architecture::batpu2::operand::Operand;
+use crate::architecture::batpu2::KEYWORDS;
+use crate::parser::helpers::skip;
+use crate::parser::wrappers::parse_instruction_alias;
+use crate::source::SourceLocation;
+
+/// The pseudo-instructions every project has, written the same way as a project's own aliases.
+/// The branch mnemonics have no opcode of their own, the listing still shows them
+const BUILTIN: [&str; 19] = [
+    "cmp a, b = SUB a, b, r0",
+    "mov a, c = ADD a, r0, c",
+    "lsh a, c = ADD a, a, c",
+    "inc a = ADI a, 1",
+    "dec a = ADI a, -1",
+    "not a, c = NOR a, r0, c",
+    "neg b, c = SUB r0, b, c",
+    "beq a = BRH eq, a",
+    "bzs a = BRH eq, a",
+    "bz a = BRH eq, a",
+    "bne a = BRH ne, a",
+    "bzc a = BRH ne, a",
+    "bnz a = BRH ne, a",
+    "bge a = BRH ge, a",
+    "bcs a = BRH ge, a",
+    "bc a = BRH ge, a",
+    "blt a = BRH lt, a",
+    "bcc a = BRH lt, a",
+    "bnc a = BRH lt, a"
+];
+
+#[derive(Debug, Error)]
+pub enum InstructionError {
+    #[error("Cannot read instruction aliases from {path}: {error}")]
+    Unreadable {
+        path: String,
+        error: std::io::Error
+    },
+    #[error("{location}: Syntax error in instruction alias\n{reason}")]
+    FailedToParse {
+        location: SourceLocation,
+        reason: String
+    },
+    #[error("{location}: {name} is already an instruction or keyword")]
+    Taken {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: {opcode} cannot be aliased, only single instructions can")]
+    UnsupportedTarget {
+        location: SourceLocation,
+        opcode: Opcode
+    },
+    #[error("{location}: {opcode} takes {expected}")]
+    WrongOperands {
+        location: SourceLocation,
+        opcode: Opcode,
+        expected: String
+    },
+    #[error("{location}: {name} is not a parameter of {alias}")]
+    UnknownParameter {
+        location: SourceLocation,
+        name: String,
+        alias: String
+    }
+}
+
+/// What each operand of `opcode` is, pseudo-instructions like `MOV` taking theirs from the
+/// instruction their built-in alias stands for
+pub fn operand_fields(opcode: Opcode) -> Option<Vec<Field>> {
+    match opcode.fields() {
+        Some(fields) => Some(fields.to_vec()),
+        None => InstructionSet::builtin(&opcode.to_string())
+            .map(InstructionAlias::fields)
+    }
+}
+
+/// An operand of the instruction an alias stands for
+#[derive(Debug, Clone, PartialEq)]
+enum Slot {
+    Parameter(usize),
+    Fixed(Operand)
+}
+
+/// A single instruction under another name, like `clr a = XOR a, a, a`. The parameters take the
+/// place of their names in the instruction, and are whatever operand they stand in for there
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionAlias {
+    pub name: String,
+    /// What each parameter stands in for, if it is used
+    fields: Vec<Option<Field>>,
+    /// What the alias stands for
+    pub opcode: Opcode,
+    operands: Vec<Slot>
+}
+
+impl InstructionAlias {
+    pub fn parameters(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// What each parameter stands in for, a parameter that is never used is taken as a register
+    pub fn fields(&self) -> Vec<Field> {
+        self.fields.iter().map(|field| field.unwrap_or(Field::A(Access::Read))).collect()
+    }
+
+    /// The parameter that operand `index` of the instruction comes from, if any
+    pub fn parameter_of(&self, index: usize) -> Option<usize> {
+        match self.operands.get(index) {
+            Some(Slot::Parameter(parameter)) => Some(*parameter),
+            _ => None
+        }
+    }
+
+    /// The instruction the alias stands for, with `arguments` in place of the parameters. Only a
+    /// trailing offset can be left out, like it can for `LOD`, and it is then 0
+    pub fn expand(&self, arguments: &[Operand]) -> Instruction {
+        let mut temp = Instruction::new(self.opcode);
+        for slot in &self.operands {
+            temp.add_operand(match slot {
+                Slot::Parameter(parameter) => arguments.get(*parameter).cloned()
+                    .unwrap_or(Operand::Offset(Offset::new(0).unwrap())),
+                Slot::Fixed(operand) => operand.clone()
+            });
+        }
+        temp
+    }
+}
+
+/// The instruction aliases of a project, on top of the built-in pseudo-instructions like `MOV`
+#[derive(Debug, Clone, Default)]
+pub struct InstructionSet {
+    aliases: Vec<InstructionAlias>
+}
+
+impl InstructionSet {
+    pub fn new() -> InstructionSet {
+        InstructionSet {
+            aliases: Vec::new()
+        }
+    }
+
+    /// Reads a project's config, one alias per line:
+    ///
+    /// ```text
+    /// clr a = XOR a, a, a
+    /// tst a = ADD r0, a, r0   // sets the flags for a
+    /// ```
+    pub fn load(path: &Path) -> Result<InstructionSet, InstructionError> {
+        info!("Loading instruction aliases: {}", path.display());
+        let name = path.display().to_string();
+        let contents = fs::read_to_string(path).map_err(|error| InstructionError::Unreadable {
+            path: name.clone(),
+            error
+        })?;
+
+        let mut instructions = InstructionSet::new();
+        instructions.define(&name, &contents)?;
+        Ok(instructions)
+    }
+
+    /// Adds the aliases in `contents`, `file` is what errors show as the file
+    pub fn define(&mut self, file: &str, contents: &str) -> Result<(), InstructionError> {
+        let mut input = contents;
+        loop {
+            input = match skip(input) {
+                Ok((rest, _)) => rest,
+                Err(_) => input
+            };
+            if input.is_empty() {
+                break;
+            }
+
+            let location = SourceLocation::from_offset(file, contents, contents.len() - input.len());
+            let (rest, alias) = definition(file, contents, input)?;
+            if KEYWORDS.contains(&alias.name.as_str()) || self.find(&alias.name).is_some() {
+                return Err(InstructionError::Taken {
+                    location,
+                    name: alias.name
+                });
+            }
+            trace!("Found instruction alias: {}", alias.name);
+            self.aliases.push(alias);
+            input = rest;
+        }
+        debug!("{} instruction aliases defined", self.aliases.len());
+        Ok(())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&InstructionAlias> {
+        let name = name.to_lowercase();
+        self.aliases.iter().find(|a| a.name == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.aliases.iter().map(|a| a.name.as_str())
+    }
+
+    /// What a built-in pseudo-instruction like `MOV` or `BNE` stands for
+    pub fn builtin(name: &str) -> Option<&'static InstructionAlias> {
+        static BUILTINS: OnceLock<Vec<InstructionAlias>> = OnceLock::new();
+        let name = name.to_lowercase();
+        BUILTINS
+            .get_or_init(|| {
+                BUILTIN
+                    .iter()
+                    .map(|source| definition("built-in", source, source).expect("Built-in instruction aliases are valid").1)
+                    .collect()
+            })
+            .iter()
+            .find(|alias| alias.name == name)
+    }
+}
+
+/// Parses and checks the alias at the start of `input`
+fn definition<'a>(file: &str, contents: &str, input: &'a str) -> Result<(&'a str, InstructionAlias), InstructionError> {
+    let locate = |remaining: usize| SourceLocation::from_offset(file, contents, contents.len() - remaining);
+    let (rest, (name, parameters, (opcode_at, opcode), operands)) = match parse_instruction_alias(input) {
+        Ok(parsed) => parsed,
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            let remaining = e.errors.first().map_or(input.len(), |(rest, _)| rest.len());
+            return Err(InstructionError::FailedToParse {
+                location: locate(remaining),
+                reason: convert_error(contents, e)
+            });
+        },
+        Err(nom::Err::Incomplete(_)) => unreachable!("The parser only works on complete input")
+    };
+
+    let fields = operand_fields(opcode).ok_or_else(|| InstructionError::UnsupportedTarget {
+        location: locate(opcode_at),
+        opcode
+    })?;
+    let optional = fields.last() == Some(&Field::Offset) && operands.len() == fields.len() - 1;
+    if operands.len() != fields.len() && !optional {
+        return Err(InstructionError::WrongOperands {
+            location: locate(opcode_at),
+            opcode,
+            expected: expected(&fields)
+        });
+    }
+
+    let mut slots = Vec::with_capacity(operands.len());
+    let mut parameter_fields: Vec<Option<Field>> = vec![None; parameters.len()];
+    for ((at, operand), field) in operands.into_iter().zip(&fields) {
+        let slot = match (operand, field) {
+            (Operand::Name(name), _) if parameters.contains(&name.as_str()) => {
+                let index = parameters.iter().position(|p| *p == name).unwrap();
+                parameter_fields[index] = Some(match parameter_fields[index] {
+                    // A register in two places is read or written in both
+                    Some(used) => match (used.access(), field.access()) {
+                        (Some(a), Some(b)) => with_access(used, a.and(b)),
+                        _ => used
+                    },
+                    None => *field
+                });
+                Some(Slot::Parameter(index))
+            },
+            (Operand::Name(name), Field::Condition) if Condition::from_str(&name).is_ok() => {
+                Some(Slot::Fixed(Operand::Cond(Condition::from_str(&name).unwrap())))
+            },
+            (Operand::Name(parameter), _) => {
+                return Err(InstructionError::UnknownParameter {
+                    location: locate(at),
+                    name: parameter,
+                    alias: name.to_lowercase()
+                });
+            },
+            (Operand::Reg(register), Field::A(_) | Field::B(_) | Field::C(_)) => Some(Slot::Fixed(Operand::Reg(register))),
+            (Operand::Imm(value), Field::Immediate) => Some(Slot::Fixed(Operand::Imm(value))),
+            (Operand::Imm(value), Field::Offset) => i8::try_from(value.value())
+                .ok()
+                .and_then(Offset::new)
+                .map(|offset| Slot::Fixed(Operand::Offset(offset))),
+            _ => None
+        };
+        match slot {
+            Some(slot) => slots.push(slot),
+            None => {
+                return Err(InstructionError::WrongOperands {
+                    location: locate(at),
+                    opcode,
+                    expected: expected(&fields)
+                });
+            }
+        }
+    }
+
+    for (index, parameter) in parameters.iter().enumerate() {
+        if !slots.contains(&Slot::Parameter(index)) {
+            warn!("{}: Parameter {} of {} is never used", locate(input.len()), parameter, name);
+        }
+    }
+
+    Ok((rest, InstructionAlias {
+        name: name.to_lowercase(),
+        fields: parameter_fields,
+        opcode,
+        operands: slots
+    }))
+}
+
+fn with_access(field: Field, access: Access) -> Field {
+    match field {
+        Field::A(_) => Field::A(access),
+        Field::B(_) => Field::B(access),
+        Field::C(_) => Field::C(access),
+        _ => field
+    }
+}
+
+fn expected(fields: &[Field]) -> String {
+    match fields {
+        [] => "no operands".to_string(),
+        _ => fields.iter().map(Field::name).collect::<Vec<_>>().join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::batpu2::operand::label::Label;
+    use crate::architecture::batpu2::operand::register::Register;
+
+    #[test]
+    fn define() {
+        let mut instructions = InstructionSet::new();
+        let defined = instructions.define("aliases.cfg", "clr a = XOR a, a, a\npeek base, data = LOD base, data, 1 // with an offset\n");
+        assert!(defined.is_ok(), "Failed to define instruction aliases: {}", defined.err().unwrap());
+        assert_eq!(instructions.names().collect::<Vec<_>>(), ["clr", "peek"]);
+
+        let clr = instructions.find("CLR").expect("Aliases are found by any case");
+        let expanded = clr.expand(&[Operand::Reg(Register::R3)]);
+        assert_eq!(expanded.opcode, Opcode::XOR);
+        assert_eq!(expanded.operands, vec![Operand::Reg(Register::R3); 3]);
+
+        let peek = instructions.find("peek").unwrap();
+        assert_eq!(peek.parameters(), 2);
+        let expanded = peek.expand(&[Operand::Reg(Register::R1), Operand::Reg(Register::R2)]);
+        assert_eq!(expanded.operands[2], Operand::Offset(Offset::new(1).unwrap()));
+
+        let defined = instructions.define("aliases.cfg", "jz target = BRH zero, target");
+        assert!(defined.is_ok(), "Failed to define a branch alias: {}", defined.err().unwrap());
+        let jz = instructions.find("jz").unwrap();
+        assert_eq!(jz.fields(), [Field::Address]);
+        let expanded = jz.expand(&[Operand::Label(Label::new(".loop".to_string()))]);
+        assert_eq!(expanded.opcode, Opcode::BRH);
+        assert_eq!(expanded.operands[0], Operand::Cond(Condition::Z1));
+    }
+
+    #[test]
+    fn builtin() {
+        let bnz = InstructionSet::builtin("BNZ").expect("Branch mnemonics are built-in aliases");
+        assert_eq!(bnz.opcode, Opcode::BRH);
+        assert_eq!(bnz.expand(&[Operand::Label(Label::new(".loop".to_string()))]).operands[0], Operand::Cond(Condition::Z0));
+        assert_eq!(operand_fields(Opcode::MOV), Some(vec![Field::A(Access::Read), Field::C(Access::Write)]));
+        assert_eq!(operand_fields(Opcode::MUL), None);
+    }
+
+    #[test]
+    fn define_errors() {
+        for (contents, error) in [
+            ("mov a, b = ADD a, r0, b", "aliases.cfg:1:1: mov is already an instruction or keyword"),
+            ("clr a = XOR a, a, a\nclr b = NOR b, b, b", "aliases.cfg:2:1: clr is already an instruction or keyword"),
+            ("clr a = XOR a, a", "aliases.cfg:1:9: xor takes register, register, register"),
+            ("clr a = XOR a, a, b", "aliases.cfg:1:19: b is not a parameter of clr"),
+            ("stop = HLT r1", "aliases.cfg:1:8: hlt takes no operands"),
+            ("jz a = BRH a", "aliases.cfg:1:8: brh takes condition, address"),
+            ("big a = MUL a, a", "aliases.cfg:1:9: mul cannot be aliased")
+        ] {
+            let defined = InstructionSet::new().define("aliases.cfg", contents);
+            let message = defined.expect_err("An invalid instruction alias was accepted").to_string();
+            assert!(message.contains(error), "Expected \"{}\" in: {}", error, message);
+        }
+    }
+}
@@ -1,29 +1,95 @@
+use thiserror::Error;
 use crate::architecture::batpu2::instruction::Instruction;
 use crate::architecture::batpu2::opcode::Opcode;
 use crate::architecture::batpu2::operand::immediate::Address;
+use crate::architecture::batpu2::operand::Operand;
+use crate::source::SourceLocation;
 
-pub fn layout_program(program: &mut [Instruction]) {
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error("{location}: Program does not fit in program memory, this would be at 0x{address:04X} (the last address is 0x07FE)")]
+    ProgramTooLarge {
+        location: SourceLocation,
+        address: u16
+    },
+    #[error("{location}: .org {address} overlaps the code before it, which ends at 0x{end:04X}")]
+    Overlap {
+        location: SourceLocation,
+        address: Address,
+        end: u16
+    }
+}
+
+/// Gives every instruction its address. `.org` and `.align` skip ahead, and the gaps they leave
+/// are filled with the filler instruction, `NOP` unless set with `.filler`
+pub fn layout_program(program: Vec<Instruction>) -> Result<Vec<Instruction>, LayoutError> {
     info!("Laying out program...");
-    let mut current_address = 0;
-    for instruction in program {
-        match Address::new(current_address) {
-            Some(addr) => {
-                match instruction.opcode {
-                    Opcode::_Label => {
-                        instruction.location = Some(addr);
-                        continue;
-                    },
-                    Opcode::_Definition => {
-                        continue;
-                    },
-                    _ => {
-                        instruction.location = Some(addr);
-                        current_address += 2;
-                    }
+    let mut laid_out: Vec<Instruction> = Vec::with_capacity(program.len());
+    let mut filler: Option<Instruction> = None;
+    let mut current_address: u16 = 0;
+
+    for mut instruction in program {
+        let target = match (instruction.opcode, instruction.operands.first()) {
+            (Opcode::_Org, Some(Operand::Addr(addr))) => {
+                let target = addr.value() << 1;
+                if target < current_address {
+                    return Err(LayoutError::Overlap {
+                        location: instruction.source.clone(),
+                        address: *addr,
+                        end: current_address
+                    });
                 }
+                Some(target)
+            },
+            (Opcode::_Align, Some(Operand::Count(bytes))) => Some(current_address.next_multiple_of(*bytes)),
+            (Opcode::_Filler, Some(Operand::Instr(repeated))) => {
+                filler = Some((**repeated).clone());
+                None
             },
-            None => panic!("Attempted to assign a invalid address: {:04X}", current_address)
+            _ => None
+        };
+
+        match instruction.opcode {
+            Opcode::_Label => {
+                instruction.location = Some(address(current_address, &instruction.source)?);
+            },
+            op if op.is_directive() => {},
+            _ => {
+                instruction.location = Some(address(current_address, &instruction.source)?);
+                current_address += 2;
+            }
+        }
+
+        let source = instruction.source.clone();
+        laid_out.push(instruction);
+
+        if let Some(target) = target {
+            trace!("Filling 0x{:04X} to 0x{:04X}", current_address, target);
+            while current_address < target {
+                let mut gap = filler.clone().unwrap_or_else(|| {
+                    let mut nop = Instruction::new(Opcode::NOP);
+                    nop.source = source.clone();
+                    nop
+                });
+                gap.location = Some(address(current_address, &source)?);
+                laid_out.push(gap);
+                current_address += 2;
+            }
         }
     }
+
     debug!("Program size: {} bytes", current_address);
-}
\ No newline at end of file
+    Ok(laid_out)
+}
+
+fn address(address: u16, location: &SourceLocation) -> Result<Address, LayoutError> {
+    match Address::new(address) {
+        Some(addr) => Ok(addr),
+        None => {
+            Err(LayoutError::ProgramTooLarge {
+                location: location.clone(),
+                address
+            })
+        }
+    }
+}
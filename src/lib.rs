@@ -1,10 +1,21 @@
 pub mod architecture;
 pub mod parser;
+pub mod alias;
+pub mod expand;
+pub mod instructions;
+pub mod library;
+pub mod registers;
+pub mod memory;
 pub mod layout;
 pub mod resolve;
 pub mod eval;
 pub mod encode;
 pub mod print;
+pub mod symbols;
+pub mod source;
+pub mod sprite;
+pub mod diagnostics;
+pub mod suggest;
 
 extern crate pretty_env_logger;
 #[macro_use] extern crate log;
@@ -12,60 +23,84 @@ extern crate pretty_env_logger;
 use std::{fs, io};
 use std::path::PathBuf;
 use anyhow::Error;
+use crate::architecture::batpu2::charset::Charset;
 use crate::architecture::batpu2::instruction::Instruction;
+use crate::diagnostics::Diagnostics;
 use crate::encode::InstructionEncoder;
+use crate::expand::Expander;
+use crate::instructions::InstructionSet;
+use crate::parser::ParseError;
 use crate::print::AssemblyPrinter;
+use crate::symbols::SymbolMap;
 
 pub struct Assembler {
     pub size: Option<u16>,
+    /// Character literals use the display's character codes unless set to [`Charset::Ascii`]
+    pub charset: Charset,
+    /// The project's own instruction aliases, see [`InstructionSet::load`]
+    pub instructions: InstructionSet
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Assembler {
     pub fn new() -> Assembler {
         Assembler {
-            size: None
+            size: None,
+            charset: Charset::default(),
+            instructions: InstructionSet::new()
         }
     }
 
     pub fn assemble(&self, input_path: &[PathBuf], output_path: PathBuf) -> anyhow::Result<(Vec<Instruction>, Vec<u8>)> {
         let mut program: Vec<Instruction> = Vec::new();
+        let mut diagnostics = Diagnostics::new();
+        let mut truncated = false;
         let input_files: Vec<PathBuf> = input_path
             .iter()
             .filter(|p| p.is_file())
-            .map(|p| p.clone())
+            .cloned()
             .collect();
 
         for file_path in input_files {
             // there could be multiple .asm files, but we compile to one binary
-            match parser::parse(file_path) {
-                Ok(code) => program.extend(code),
+            match parser::parse(file_path, &self.instructions) {
+                Ok((code, errors)) => {
+                    truncated |= errors.iter().any(|e| matches!(e, ParseError::TooManyErrors(_)));
+                    program.extend(code);
+                    diagnostics.extend(errors);
+                },
                 Err(e) => return Err(Error::from(e))
             };
         }
 
-        layout::layout_program(&mut program);
-
-        match resolve::resolve_program(&mut program) {
-            Ok(_) => {
-                debug!("Program resolved successfully");
-            },
-            Err(e) => {
-                error!("Failed to resolve program");
-                return Err(Error::from(e));
-            }
+        // Whatever did parse is still checked, so its errors are reported along with the syntax errors.
+        // A file that was only partly parsed would report errors for everything that was cut off
+        if truncated {
+            error!("Failed to parse program");
+            return Err(Error::from(diagnostics));
         }
 
-        match eval::evaluate_program(&mut program) {
-            Ok(_) => {
-                debug!("Program evaluated successfully");
-            },
+        let mut program = match self.analyze(program) {
+            Ok(p) => p,
+            Err(e) if diagnostics.is_empty() => return Err(e),
             Err(e) => {
-                error!("Failed to evaluate program");
-                return Err(Error::from(e));
+                diagnostics.push(e);
+                return Err(Error::from(diagnostics));
             }
+        };
+
+        if !diagnostics.is_empty() {
+            error!("Failed to parse program");
+            return Err(Error::from(diagnostics));
         }
 
         let mut encoder = InstructionEncoder::new();
+        encoder.charset = self.charset;
 
         match encoder.encode_program(&mut program) {
             Ok(_) => {
@@ -95,12 +130,111 @@ impl Assembler {
         Ok((program, binary))
     }
 
+    /// Runs every stage between parsing and encoding
+    fn analyze(&self, program: Vec<Instruction>) -> anyhow::Result<Vec<Instruction>> {
+        let mut program = match library::include_libraries(program) {
+            Ok(p) => {
+                debug!("Libraries included successfully");
+                p
+            },
+            Err(e) => {
+                error!("Failed to include libraries");
+                return Err(Error::from(e));
+            }
+        };
+
+        match alias::resolve_aliases(&mut program) {
+            Ok(_) => {
+                debug!("Aliases resolved successfully");
+            },
+            Err(e) => {
+                error!("Failed to resolve aliases");
+                return Err(Error::from(e));
+            }
+        }
+
+        let mut expander = Expander::new();
+        program = match expander.expand_program(program) {
+            Ok(p) => {
+                debug!("Program expanded successfully");
+                p
+            },
+            Err(e) => {
+                error!("Failed to expand program");
+                return Err(Error::from(e));
+            }
+        };
+
+        program = match registers::allocate_registers(program) {
+            Ok(p) => {
+                debug!("Registers allocated successfully");
+                p
+            },
+            Err(e) => {
+                error!("Failed to allocate registers");
+                return Err(Error::from(e));
+            }
+        };
+
+        match memory::layout_structs(&mut program) {
+            Ok(_) => {
+                debug!("Structs laid out successfully");
+            },
+            Err(e) => {
+                error!("Failed to lay out structs");
+                return Err(Error::from(e));
+            }
+        }
+
+        match memory::allocate_variables(&mut program) {
+            Ok(_) => {
+                debug!("Variables allocated successfully");
+            },
+            Err(e) => {
+                error!("Failed to allocate variables");
+                return Err(Error::from(e));
+            }
+        }
+
+        program = match layout::layout_program(program) {
+            Ok(p) => {
+                debug!("Program laid out successfully");
+                p
+            },
+            Err(e) => {
+                error!("Failed to lay out program");
+                return Err(Error::from(e));
+            }
+        };
+
+        match resolve::resolve_program(&mut program) {
+            Ok(_) => {
+                debug!("Program resolved successfully");
+            },
+            Err(e) => {
+                error!("Failed to resolve program");
+                return Err(Error::from(e));
+            }
+        }
+
+        match eval::evaluate_program(&mut program) {
+            Ok(_) => {
+                debug!("Program evaluated successfully");
+            },
+            Err(e) => {
+                error!("Failed to evaluate program");
+                return Err(Error::from(e));
+            }
+        }
+
+        Ok(program)
+    }
+
     fn convert_program_to_bytes(&self, program: &[Instruction]) -> Result<Vec<u8>, io::Error> {
         debug!("Creating binary");
-        let mut binary: Vec<u8> = if self.size.is_some() {
-            Vec::with_capacity(self.size.unwrap() as usize)
-        } else {
-            Vec::new()
+        let mut binary: Vec<u8> = match self.size {
+            Some(size) => Vec::with_capacity(size as usize),
+            None => Vec::new()
         };
 
         for instruction in program {
@@ -111,14 +245,12 @@ impl Assembler {
             }
         }
 
-        if self.size.is_some() {
-            if binary.len() > self.size.unwrap() as usize {
-                error!("Binary size ({}) exceeds specified size ({})", binary.len(), self.size.unwrap());
+        if let Some(size) = self.size {
+            if binary.len() > size as usize {
+                error!("Binary size ({}) exceeds specified size ({})", binary.len(), size);
                 return Err(io::Error::new(io::ErrorKind::InvalidInput, "Binary size exceeds specified size"));
             }
-            while binary.len() < self.size.unwrap() as usize {
-                binary.push(0);
-            }
+            binary.resize(size as usize, 0);
         }
 
         trace!("Binary created");
@@ -126,18 +258,21 @@ impl Assembler {
     }
 
     pub fn print_program(&self, program: &[Instruction]) {
-        let mut printer = AssemblyPrinter::new(&program);
+        let mut printer = AssemblyPrinter::new(program);
         info!("Printing assembly: \n{}", printer.print());
     }
 
+    pub fn print_symbols(&self, program: &[Instruction]) {
+        let map = SymbolMap::new(program);
+        info!("Printing symbols: \n{}", map.print());
+    }
+
     pub fn hex_dump(&self, bin: &[u8]) {
-        let mut i = 0;
-        for byte in bin {
+        for (i, byte) in bin.iter().enumerate() {
             if i % 16 == 0 {
                 println!("\n{:04X} |", i);
             }
             print!("{:02X} ", byte);
-            i += 1;
         }
     }
 }
@@ -145,35 +280,296 @@ impl Assembler {
 #[cfg(test)]
 mod tests { // TODO: Finish writing all the tests... again
     use super::*;
-    
+    use std::path::Path;
+
+    /// Assembles `test_data/{file}.asm` for every file, into a binary named after the first one
+    fn try_assemble(assembler: &Assembler, files: &[&str]) -> anyhow::Result<(Vec<Instruction>, Vec<u8>)> {
+        let input_path: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(format!("./test_data/{f}.asm"))).collect();
+        let output_path = PathBuf::from(format!("./test_data/{}.bin", files[0]));
+        assembler.assemble(&input_path, output_path)
+    }
+
+    fn assemble(assembler: &Assembler, files: &[&str]) -> (Vec<Instruction>, Vec<u8>) {
+        let test = try_assemble(assembler, files);
+        assert!(test.is_ok(), "Failed to assemble {}: {}", files.join(", "), test.err().unwrap());
+        test.unwrap()
+    }
+
+    /// Checks that `file` assembles to the same binary as `expected`, which is usually the same
+    /// program written out by hand. Returns the program of `file`
+    fn assert_same_binary(assembler: &Assembler, file: &str, expected: &str) -> Vec<Instruction> {
+        let (program, binary) = assemble(assembler, &[file]);
+        let (_, expected_binary) = assemble(assembler, &[expected]);
+        assert_eq!(binary, expected_binary, "{file}.asm did not assemble to the same binary as {expected}.asm");
+        program
+    }
+
+    /// Checks that `file` fails to assemble with an error containing `message`, and returns the whole error
+    fn assert_error(assembler: &Assembler, file: &str, message: &str) -> String {
+        let error = try_assemble(assembler, &[file])
+            .expect_err(&format!("{file}.asm was accepted"))
+            .to_string();
+        assert!(error.contains(message), "Expected \"{}\" in: {}", message, error);
+        error
+    }
+
     #[test]
     fn ai_generated() {
-        let input_path = vec![PathBuf::from("./test_data/ai_generated/test.asm")];
-        let output_path = PathBuf::from("./test_data/ai_generated/test.asm");
+        assemble(&Assembler::new(), &["ai_generated/test"]);
+    }
+
+    #[test]
+    fn different_files() {
+        assert_same_binary(&Assembler::new(), "different_files/alpha", "different_files/beta");
+    }
+
+    #[test]
+    fn local_labels() {
+        assert_same_binary(&Assembler::new(), "local_labels/scoped", "local_labels/unique");
+    }
+
+    #[test]
+    fn address_expressions() {
         let assembler = Assembler::new();
+        assert_same_binary(&assembler, "expressions/arithmetic", "expressions/plain");
+        assert_same_binary(&assembler, "expressions/operators", "expressions/plain");
+        assert_error(&assembler, "expressions/out_of_range", "is out of range");
+    }
 
-        let test = assembler.assemble(&input_path, output_path);
-        assert!(test.is_ok(), "Failed to assemble program: {}", test.err().unwrap());
+    #[test]
+    fn register_aliases() {
+        let program = assert_same_binary(&Assembler::new(), "aliases/aliases", "aliases/registers");
+
+        let listing = AssemblyPrinter::new(&program).print();
+        assert!(listing.contains("cursor_x (r1)"), "Alias name missing from listing:\n{}", listing);
     }
 
     #[test]
-    fn different_files() {
-        let input_path = vec![PathBuf::from("./test_data/different_files/alpha.asm")];
-        let output_path = PathBuf::from("./test_data/different_files/alpha.bin");
+    fn assertions() {
         let assembler = Assembler::new();
+        assemble(&assembler, &["diagnostics/passing"]);
+        let message = assert_error(&assembler, "diagnostics/failing_assert", "failing_assert.asm:9:1");
+        assert!(message.contains("Rows do not fit in the grid"), "Missing message: {}", message);
+        assert_error(&assembler, "diagnostics/error", "error.asm:5:1: Not implemented yet");
+        assert_same_binary(&assembler, "diagnostics/directive_labels", "diagnostics/directive_labels_by_hand");
+        let message = assert_error(&assembler, "diagnostics/reserved_label", "reserved_label.asm:4:9: Syntax error");
+        assert!(message.contains("Reserved directive name"), "Unexpected error: {}", message);
+    }
+
+    #[test]
+    fn compare_tetris() {
+        let (_, binary) = assemble(&Assembler::new(), &["compare/tetris"]);
 
-        let test = assembler.assemble(&input_path, output_path);
-        assert!(test.is_ok(), "Failed to assemble program: {}", test.err().unwrap());
+        let expected: Vec<u8> = fs::read_to_string("./test_data/compare/tetris.mc")
+            .unwrap()
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .flat_map(|l| u16::from_str_radix(l.trim(), 2).unwrap().to_le_bytes())
+            .collect();
 
-        let (_, a) = test.unwrap();
+        assert_eq!(binary, expected, "Binary does not match the reference machine code");
+    }
+
+    #[test]
+    fn character_set() {
+        let mut assembler = Assembler::new();
+        assert_error(&assembler, "charset/unsupported", "cannot be shown");
+
+        assembler.charset = Charset::Ascii;
+        let (_, binary) = assemble(&assembler, &["charset/unsupported"]);
+        assert_eq!(binary[2], b'#', "Character was not encoded as ASCII");
+    }
+
+    #[test]
+    fn print_directive() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "print/game_over", "print/by_hand");
+        assert_error(&assembler, "print/too_long", "the display only fits");
+    }
+
+    #[test]
+    fn file_namespaces() {
+        let assembler = Assembler::new();
+        assemble(&assembler, &["namespaces/main", "namespaces/score"]);
+
+        let test = try_assemble(&assembler, &["namespaces/conflict_a", "namespaces/conflict_b"]);
+        let message = test.expect_err("Label exported by two files was accepted").to_string();
+        assert!(message.contains("conflict_a.asm") && message.contains("conflict_b.asm"), "Error does not name both files: {}", message);
+    }
+
+    #[test]
+    fn source_locations() {
+        let assembler = Assembler::new();
+        assert_error(&assembler, "locations/unknown_label", "unknown_label.asm:7:13");
+        assert_error(&assembler, "locations/unknown_alias", "unknown_alias.asm:6:18");
+        assert_error(&assembler, "locations/parse_error", "parse_error.asm:5:16");
+
+        let (program, _) = assemble(&assembler, &["local_labels/scoped"]);
+        let listing = AssemblyPrinter::new(&program).print();
+        assert!(listing.contains("| ./test_data/local_labels/scoped.asm:5:5"), "Source missing from listing:\n{}", listing);
+    }
+
+    #[test]
+    fn error_recovery() {
+        let assembler = Assembler::new();
+
+        let message = assert_error(&assembler, "recovery/several", "several.asm:9:13: Unknown label");
+        assert!(message.starts_with("4 errors"), "Expected 4 errors: {}", message);
+        for location in ["several.asm:5:16", "several.asm:6:14", "several.asm:10:5"] {
+            assert!(message.contains(location), "Expected {} in: {}", location, message);
+        }
+
+        let message = assert_error(&assembler, "recovery/many", "Too many syntax errors");
+        assert!(message.starts_with(&format!("{} errors", parser::MAX_SYNTAX_ERRORS + 1)), "Errors were not capped: {}", message);
+
+        let message = assert_error(&assembler, "recovery/exactly_max", "Syntax error");
+        assert!(message.starts_with(&format!("{} errors", parser::MAX_SYNTAX_ERRORS)), "Errors were miscounted: {}", message);
+        assert!(!message.contains("Too many syntax errors"), "Nothing was left to parse: {}", message);
+
+        let message = assert_error(&assembler, "recovery/truncated", "Too many syntax errors");
+        assert!(!message.contains("Unknown label"), "Label after the cap was reported: {}", message);
+    }
+
+    #[test]
+    fn suggestions() {
+        let assembler = Assembler::new();
+        assert_error(&assembler, "suggestions/label", "did you mean draw_pixel?");
+        assert_error(&assembler, "suggestions/definition", "did you mean GRID_WIDTH?");
+        assert_error(&assembler, "suggestions/port", "did you mean clear_screen_buffer?");
+        assert_error(&assembler, "suggestions/opcode", "did you mean mov?");
+    }
+
+    #[test]
+    fn typed_definitions() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "definitions/typed", "definitions/plain");
+        assert_error(&assembler, "definitions/duplicate",
+            "duplicate.asm:5:1: Definition ROWS is defined twice, first at ./test_data/definitions/duplicate.asm:3:1");
+        assert_error(&assembler, "definitions/mismatch", "mismatch.asm:6:13: START is defined with type address");
+        assert_error(&assembler, "definitions/out_of_range",
+            "out_of_range.asm:6:17: STRIDE = 12 (defined at ./test_data/definitions/out_of_range.asm:3:1) does not fit in an offset");
+    }
+
+    #[test]
+    fn variables() {
+        let assembler = Assembler::new();
+        let program = assert_same_binary(&assembler, "variables/vars", "variables/by_hand");
+
+        let symbols = SymbolMap::new(&program).print();
+        assert!(symbols.contains("array      | 0x0001 | board (200 bytes)"), "Array missing from symbol map:\n{}", symbols);
+        assert!(symbols.contains("variable   | 0x00C9 | lines"), "Variable missing from symbol map:\n{}", symbols);
+
+        assert_error(&assembler, "variables/full", "full.asm:5:1: history needs 20 bytes at address 230");
+    }
+
+    #[test]
+    fn structs() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "structs/piece", "structs/by_hand");
+        assert_error(&assembler, "structs/too_big", "too_big.asm:7:5: Field Player.level is at offset 8");
+    }
+
+    #[test]
+    fn tables() {
+        assert_same_binary(&Assembler::new(), "tables/tables", "tables/by_hand");
+    }
+
+    #[test]
+    fn layout_directives() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "layout/pinned", "layout/by_hand");
+        assert_error(&assembler, "layout/overlap", "overlap.asm:6:1: .org 0x0004 overlaps");
+        assert_error(&assembler, "layout/too_large", "too_large.asm:7:5: Program does not fit");
+        assert_same_binary(&assembler, "layout/pseudo_fill", "layout/pseudo_fill_by_hand");
+        let message = assert_error(&assembler, "layout/pseudo_filler", "pseudo_filler.asm:3:13: Syntax error");
+        assert!(message.contains("Expected a single instruction"), "Unexpected error: {}", message);
+    }
+
+    #[test]
+    fn branch_mnemonics() {
+        let program = assert_same_binary(&Assembler::new(), "branches/mnemonics", "branches/brh");
+
+        let listing = AssemblyPrinter::new(&program).print();
+        assert!(listing.contains("bne  .down"), "Branch mnemonic missing from listing:\n{}", listing);
+    }
+
+    #[test]
+    fn immediate_pseudo_instructions() {
+        assert_same_binary(&Assembler::new(), "immediates/pseudo", "immediates/by_hand");
+    }
+
+    #[test]
+    fn software_stack() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "stack/stack", "stack/by_hand");
+        assert_error(&assembler, "stack/missing", "missing.asm:2:5: push needs a stack");
+    }
+
+    #[test]
+    fn register_pairs() {
+        assert_same_binary(&Assembler::new(), "pairs/wide", "pairs/by_hand");
+    }
+
+    #[test]
+    fn multiply_and_divide() {
+        let assembler = Assembler::new();
+        let (_, arithmetic) = assemble(&assembler, &["arithmetic/arithmetic", "arithmetic/other"]);
+        let (_, by_hand) = assemble(&assembler, &["arithmetic/by_hand"]);
+        assert_eq!(arithmetic, by_hand, "MUL, DIV and MOD did not expand as expected");
+
+        assert_same_binary(&assembler, "arithmetic/swap", "arithmetic/swap_by_hand");
+        assert_error(&assembler, "arithmetic/zero", "zero.asm:2:13: Division by zero");
+        assert_error(&assembler, "arithmetic/swap_scratch", "swap_scratch.asm:3:5: mul swaps its operands through the scratch register");
+    }
+
+    #[test]
+    fn standard_library() {
+        let assembler = Assembler::new();
+        let program = assert_same_binary(&assembler, "library/game", "library/game_by_hand");
+
+        let included = |module: &str| program.iter().any(|i| i.source.file == format!("std/{module}.asm"));
+        for module in ["clear_screen", "draw_line", "draw_rect", "wait_input", "random_range", "print_number"] {
+            assert!(included(module), "std/{module}.asm was not included");
+        }
+        for module in ["fill_rect", "multiply", "divide"] {
+            assert!(!included(module), "std/{module}.asm was included without being called");
+        }
+
+        assert_same_binary(&assembler, "library/arithmetic", "library/arithmetic_by_hand");
+        assert_error(&assembler, "library/unknown", "unknown.asm:1:10: Unknown library \"maths\"");
+    }
+
+    #[test]
+    fn sprites() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "sprites/sprites", "sprites/by_hand");
+        assert_error(&assembler, "sprites/off_screen", "off_screen.asm:3:26: The 5x5 sprite does not fit");
+    }
+
+    #[test]
+    fn structured_control_flow() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "control/structured", "control/by_hand");
+        assert_same_binary(&assembler, "control/gol", "game_of_life/gol");
+        assert_error(&assembler, "control/unclosed", "unclosed.asm:4:9: .if is never closed with .endif");
+        assert_error(&assembler, "control/unopened", "unopened.asm:4:5: .endloop without an open .loop");
+    }
+
+    #[test]
+    fn virtual_registers() {
+        let assembler = Assembler::new();
+        assert_same_binary(&assembler, "virtual/virtual", "virtual/by_hand");
+        assert_same_binary(&assembler, "virtual/spill", "virtual/spill_by_hand");
+        assert_error(&assembler, "virtual/full", "full.asm:19:9: No register is left for %b");
+    }
+
+    #[test]
+    fn instruction_aliases() {
+        let mut assembler = Assembler::new();
+        let instructions = InstructionSet::load(Path::new("./test_data/instructions/project.cfg"));
+        assert!(instructions.is_ok(), "Failed to load instruction aliases: {}", instructions.err().unwrap());
+        assembler.instructions = instructions.unwrap();
 
-        let input_path = vec![PathBuf::from("./test_data/different_files/beta.asm")];
-        let output_path = PathBuf::from("./test_data/different_files/beta.bin");
-        let test = assembler.assemble(&input_path, output_path);
-        assert!(test.is_ok(), "Failed to assemble program: {}", test.err().unwrap());
-        
-        let (_, b) = test.unwrap(); 
-        
-        assert_eq!(a, b, "Binary files are not equal");
+        assert_same_binary(&assembler, "instructions/custom", "instructions/by_hand");
     }
 }
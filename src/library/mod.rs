@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use thiserror::Error;
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::operand::Operand;
+use crate::instructions::InstructionSet;
+use crate::parser::{parse_source, ParseError};
+use crate::source::SourceLocation;
+
+/// The standard library, one routine per module. Each module exports a label with its own name
+const STANDARD: [(&str, &str); 9] = [
+    ("print_number", include_str!("std/print_number.asm")),
+    ("clear_screen", include_str!("std/clear_screen.asm")),
+    ("draw_line", include_str!("std/draw_line.asm")),
+    ("draw_rect", include_str!("std/draw_rect.asm")),
+    ("fill_rect", include_str!("std/fill_rect.asm")),
+    ("wait_input", include_str!("std/wait_input.asm")),
+    ("random_range", include_str!("std/random_range.asm")),
+    ("multiply", include_str!("std/multiply.asm")),
+    ("divide", include_str!("std/divide.asm"))
+];
+
+#[derive(Debug, Error)]
+pub enum LibraryError {
+    #[error("{location}: Unknown library {name}, the standard library is \"std\"")]
+    UnknownLibrary {
+        location: SourceLocation,
+        name: Operand
+    },
+    #[error("The standard library module {module} is broken: {error}")]
+    BrokenModule {
+        module: String,
+        error: ParseError
+    }
+}
+
+/// Adds the modules of the standard library that files with `.include "std"` call to the end
+/// of the program, along with the modules those call in turn. Modules nobody calls are left out.
+/// Calls are imported like `.extern` does, except in files with a label of the same name
+pub fn include_libraries(mut program: Vec<Instruction>) -> Result<Vec<Instruction>, LibraryError> {
+    let mut including: Vec<SourceLocation> = Vec::new();
+    for instruction in program.iter().filter(|i| i.opcode == Opcode::_Include) {
+        match &instruction.operands[0] {
+            Operand::Text(name) if name == "std" => {
+                if !including.iter().any(|s| s.file == instruction.source.file) {
+                    including.push(instruction.source.clone());
+                }
+            },
+            name => {
+                return Err(LibraryError::UnknownLibrary {
+                    location: instruction.operand_source(0).clone(),
+                    name: name.clone()
+                });
+            }
+        }
+    }
+
+    if including.is_empty() {
+        return Ok(program);
+    }
+
+    info!("Including the standard library...");
+    let mut modules: Vec<(String, Vec<Instruction>)> = Vec::with_capacity(STANDARD.len());
+    for (name, source) in STANDARD {
+        let module = format!("std/{name}.asm");
+        let code = match parse_source(&module, source, &InstructionSet::new()) {
+            Ok((_, errors)) if !errors.is_empty() => {
+                return Err(LibraryError::BrokenModule {
+                    module,
+                    error: errors.into_iter().next().unwrap()
+                });
+            },
+            Ok((code, _)) => code,
+            Err(error) => return Err(LibraryError::BrokenModule { module, error })
+        };
+        modules.push((name.to_string(), code));
+    }
+    let names: Vec<String> = modules.iter().map(|(name, _)| name.clone()).collect();
+
+    let mut imports: Vec<Instruction> = Vec::new();
+    let mut needed: HashSet<String> = HashSet::new();
+    for include in &including {
+        for called in calls(&mut program, &include.file, &names) {
+            imports.push(import(&called, include));
+            needed.insert(called);
+        }
+    }
+
+    // Modules calling other modules import them the same way
+    let mut pending: Vec<String> = needed.iter().cloned().collect();
+    while let Some(name) = pending.pop() {
+        let (_, code) = modules.iter_mut().find(|(n, _)| *n == name).unwrap();
+        let file = format!("std/{name}.asm");
+        let location = code[0].source.clone();
+        for called in calls(code, &file, &names) {
+            code.push(import(&called, &location));
+            if needed.insert(called.clone()) {
+                pending.push(called);
+            }
+        }
+    }
+
+    debug!("Including {} of {} standard library modules", needed.len(), modules.len());
+    program.extend(imports);
+    for (name, code) in modules {
+        if needed.contains(&name) {
+            trace!("Including std/{}.asm", name);
+            program.extend(code);
+        }
+    }
+    Ok(program)
+}
+
+/// The modules that `file` calls without having a label of that name itself
+fn calls(program: &mut [Instruction], file: &str, modules: &[String]) -> Vec<String> {
+    let defined: HashSet<String> = program
+        .iter()
+        .filter(|i| i.opcode == Opcode::_Label && i.source.file == file)
+        .map(|i| i.operands[0].to_string())
+        .collect();
+
+    let mut called: Vec<String> = Vec::new();
+    for instruction in program.iter_mut().filter(|i| i.source.file == file) {
+        for (operand, _) in instruction.located_operands_mut() {
+            for label in operand.labels_mut() {
+                if modules.contains(&label.name) && !defined.contains(&label.name) && !called.contains(&label.name) {
+                    called.push(label.name.clone());
+                }
+            }
+        }
+    }
+    called
+}
+
+fn import(name: &str, location: &SourceLocation) -> Instruction {
+    let mut temp = Instruction::new(Opcode::_Extern);
+    temp.add_label_name(name.to_string());
+    temp.source = location.clone();
+    temp
+}
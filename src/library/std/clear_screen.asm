@@ -0,0 +1,9 @@
+// Clears the screen buffer and shows it, so the screen goes blank.
+// Overwrites r15.
+.global clear_screen
+
+.clear_screen
+    LDI r15 clear_screen_buffer
+    STR r15 r0
+    STR r15 r0 -1       // buffer_screen
+    RET
@@ -0,0 +1,9 @@
+// Divides r1 by r2 and returns the quotient in r1 and the remainder in r2.
+// Dividing by zero gives 255, with r1 as the remainder. Overwrites r3 and r12 to r15.
+.global divide
+
+.divide
+    MOD r1, r2, r3      // r1 and r2 are still needed for the quotient
+    DIV r1, r2
+    MOV r3 r2
+    RET
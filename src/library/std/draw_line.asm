@@ -0,0 +1,46 @@
+// Draws a line from (r1, r2) to (r3, r4) into the screen buffer with Bresenham's algorithm.
+// Store to buffer_screen to show it.
+// Overwrites r1 to r10, r14 and r15.
+.global draw_line
+
+.draw_line
+    LDI r15 pixel_x
+    LDI r14 128         // sign bit
+    LDI r7 1            // r7 steps x, r5 is the distance
+    SUB r3 r1 r5
+    BRH ge ..right
+    LDI r7 -1
+    SUB r1 r3 r5
+..right
+    LDI r8 1            // r8 steps y, r6 is the distance
+    SUB r4 r2 r6
+    BRH ge ..down
+    LDI r8 -1
+    SUB r2 r4 r6
+..down
+    SUB r5 r6 r9        // r9 is the error, between -r6 and r5
+..plot
+    STR r15 r1
+    STR r15 r2 1
+    STR r15 r0 2        // draw_pixel
+    CMP r1 r3
+    BRH ne ..step
+    CMP r2 r4
+    BRH eq ..done
+..step
+    ADD r9 r9 r10       // Step x when twice the error is at least -r6
+    ADD r10 r6 r10
+    AND r10 r14 r0
+    BRH ne ..vertical
+    SUB r9 r6 r9
+    ADD r1 r7 r1
+..vertical
+    SUB r5 r10 r10      // Step y when twice the error is at most r5, r10 still has r6 added
+    ADD r10 r6 r10
+    AND r10 r14 r0
+    BRH ne ..plot
+    ADD r9 r5 r9
+    ADD r2 r8 r2
+    JMP ..plot
+..done
+    RET
@@ -0,0 +1,36 @@
+// Draws the outline of a rectangle into the screen buffer. (r1, r2) is the top left corner,
+// r3 the width and r4 the height, both at least 1. Store to buffer_screen to show it.
+// Overwrites r5 to r7 and r15.
+.global draw_rect
+
+.draw_rect
+    LDI r15 pixel_x
+    ADD r1 r3 r5        // r5 is the right edge
+    DEC r5
+    ADD r2 r4 r6        // r6 is the bottom edge
+    DEC r6
+    MOV r1 r7
+..row
+    STR r15 r7
+    STR r15 r2 1
+    STR r15 r0 2        // draw_pixel
+    STR r15 r6 1
+    STR r15 r0 2
+    CMP r7 r5
+    BRH eq ..columns
+    INC r7
+    JMP ..row
+..columns
+    MOV r2 r7
+..column
+    STR r15 r7 1
+    STR r15 r1
+    STR r15 r0 2
+    STR r15 r5
+    STR r15 r0 2
+    CMP r7 r6
+    BRH eq ..done
+    INC r7
+    JMP ..column
+..done
+    RET
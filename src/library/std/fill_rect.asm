@@ -0,0 +1,29 @@
+// Fills a rectangle in the screen buffer. (r1, r2) is the top left corner, r3 the width and
+// r4 the height, both at least 1. Store to buffer_screen to show it.
+// Overwrites r5 to r8 and r15.
+.global fill_rect
+
+.fill_rect
+    LDI r15 pixel_x
+    ADD r1 r3 r5        // r5 is the right edge
+    DEC r5
+    ADD r2 r4 r6        // r6 is the bottom edge
+    DEC r6
+    MOV r2 r7
+..row
+    STR r15 r7 1
+    MOV r1 r8
+..pixel
+    STR r15 r8
+    STR r15 r0 2        // draw_pixel
+    CMP r8 r5
+    BRH eq ..next
+    INC r8
+    JMP ..pixel
+..next
+    CMP r7 r6
+    BRH eq ..done
+    INC r7
+    JMP ..row
+..done
+    RET
@@ -0,0 +1,7 @@
+// Multiplies r1 by r2 and returns the low byte of the product in r1.
+// Overwrites r12 to r15.
+.global multiply
+
+.multiply
+    MUL r1, r2
+    RET
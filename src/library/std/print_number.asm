@@ -0,0 +1,10 @@
+// Shows r1 on the number display as an unsigned number (0 to 255).
+// The character display has no digits, so numbers go to the number display.
+// Overwrites r15.
+.global print_number
+
+.print_number
+    LDI r15 show_number
+    STR r15 r0 3        // unsigned_mode
+    STR r15 r1
+    RET
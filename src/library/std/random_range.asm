@@ -0,0 +1,9 @@
+// Returns a random number from 0 up to, but not including, r1 in r1. r1 must not be 0.
+// Overwrites r2 and r12 to r15.
+.global random_range
+
+.random_range
+    LDI r15 rng
+    LOD r15 r2
+    MOD r2, r1, r1
+    RET
@@ -0,0 +1,11 @@
+// Waits until a button is pressed and returns the controller input in r1.
+// Overwrites r15.
+.global wait_input
+
+.wait_input
+    LDI r15 controller_input
+..wait
+    LOD r15 r1
+    CMP r1 r0
+    BRH eq ..wait
+    RET
@@ -11,6 +11,7 @@ fn main() {
             println!("Program assembled successfully");
 
             assembler.print_program(&prog);
+            assembler.print_symbols(&prog);
             assembler.hex_dump(&bin);
         },
         Err(e) => {
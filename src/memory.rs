@@ -0,0 +1,147 @@
+use thiserror::Error;
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::operand::definition::{Definition, DefinitionKind};
+use crate::architecture::batpu2::operand::immediate::Offset;
+use crate::architecture::batpu2::operand::Operand;
+use crate::architecture::batpu2::operand::port::Port;
+use crate::source::SourceLocation;
+
+/// Data memory ends where the I/O ports start
+pub const DATA_MEMORY_SIZE: u16 = Port::PixelX as u16;
+
+#[derive(Debug, Error)]
+pub enum MemoryError {
+    #[error("{location}: {name} needs {length} bytes at address {address}, but data memory ends at {DATA_MEMORY_SIZE} where the I/O ports start")]
+    OutOfMemory {
+        location: SourceLocation,
+        name: String,
+        address: u16,
+        length: u16
+    },
+    #[error("{location}: Field {name} is at offset {offset}, but LOD and STR offsets only go up to 7")]
+    FieldOutOfRange {
+        location: SourceLocation,
+        name: String,
+        offset: u16
+    },
+    #[error("{location}: {directive} is not inside a .struct")]
+    OutsideStruct {
+        location: SourceLocation,
+        directive: Opcode
+    },
+    #[error("{location}: Struct {name} is missing its .endstruct")]
+    UnterminatedStruct {
+        location: SourceLocation,
+        name: String
+    }
+}
+
+/// Gives every `.var`, `.array`, `.table` and `.stack` the next free data memory address, in program order.
+/// The addresses become the values of their definitions
+pub fn allocate_variables(program: &mut [Instruction]) -> Result<(), MemoryError> {
+    info!("Allocating variables...");
+    let mut next: u16 = 0;
+
+    for instruction in program.iter_mut() {
+        let length = match (instruction.opcode, instruction.operands.get(1)) {
+            (Opcode::_Var, _) => 1,
+            (Opcode::_Array | Opcode::_Stack, Some(Operand::Imm(length))) => length.value() as u16,
+            (Opcode::_Table, _) => (instruction.operands.len() - 1) as u16,
+            _ => continue
+        };
+
+        if let Operand::Def(def) = &mut instruction.operands[0] {
+            if next + length > DATA_MEMORY_SIZE {
+                return Err(MemoryError::OutOfMemory {
+                    location: instruction.source.clone(),
+                    name: def.name.clone(),
+                    address: next,
+                    length
+                });
+            }
+
+            trace!("Allocated {} bytes for {} at {}", length, def.name, next);
+            def.value = Some(next as i16);
+            def.kind = Some(DefinitionKind::Immediate);
+            next += length;
+        }
+    }
+
+    debug!("{} of {} bytes of data memory allocated", next, DATA_MEMORY_SIZE);
+    Ok(())
+}
+
+/// Gives every `.field` its offset from the start of its `.struct`, named `struct.field`, and
+/// turns `.endstruct` into the definition `struct.size`
+pub fn layout_structs(program: &mut [Instruction]) -> Result<(), MemoryError> {
+    info!("Laying out structs...");
+    // The struct being declared, where it starts and the offset of the next field
+    let mut current: Option<(String, SourceLocation, u16)> = None;
+
+    for instruction in program.iter_mut() {
+        match instruction.opcode {
+            Opcode::_Struct => {
+                if let Some((name, location, _)) = current {
+                    return Err(MemoryError::UnterminatedStruct { location, name });
+                }
+                current = Some((instruction.operands[0].to_string(), instruction.source.clone(), 0));
+            },
+            Opcode::_Field => {
+                let (name, _, offset) = match &mut current {
+                    Some(c) => c,
+                    None => {
+                        return Err(MemoryError::OutsideStruct {
+                            location: instruction.source.clone(),
+                            directive: instruction.opcode
+                        });
+                    }
+                };
+                let length = match instruction.operands.get(1) {
+                    Some(Operand::Imm(length)) => length.value() as u16,
+                    _ => 1
+                };
+
+                if let Operand::Def(def) = &mut instruction.operands[0] {
+                    def.name = format!("{}.{}", name, def.name);
+                    if *offset > Offset::MAX as u16 {
+                        return Err(MemoryError::FieldOutOfRange {
+                            location: instruction.source.clone(),
+                            name: def.name.clone(),
+                            offset: *offset
+                        });
+                    }
+
+                    trace!("Field {} is at offset {}", def.name, offset);
+                    def.value = Some(*offset as i16);
+                    def.kind = Some(DefinitionKind::Offset);
+                    *offset += length;
+                }
+            },
+            Opcode::_EndStruct => {
+                let (name, _, size) = match current.take() {
+                    Some(c) => c,
+                    None => {
+                        return Err(MemoryError::OutsideStruct {
+                            location: instruction.source.clone(),
+                            directive: instruction.opcode
+                        });
+                    }
+                };
+
+                trace!("Struct {} is {} bytes", name, size);
+                let size = Definition::new_def(&format!("{}.size", name), size as i16, Some(DefinitionKind::Immediate));
+                if let Some(size) = size {
+                    instruction.operands.clear();
+                    instruction.add_definition(size);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    match current {
+        Some((name, location, _)) => Err(MemoryError::UnterminatedStruct { location, name }),
+        None => Ok(())
+    }
+}
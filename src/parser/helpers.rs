@@ -13,7 +13,8 @@ use nom::{
             alphanumeric1,
             multispace1,
             space0,
-            space1
+            space1,
+            line_ending
         }
     },
     branch::{
@@ -92,6 +93,17 @@ fn whitespace(input: &str) -> Res<&str, &str> {
     multispace1(input)
 }
 
+/// Runs `inner` and also returns how much input was left before it, which is turned into a
+/// line and column once the whole file is parsed
+pub fn spanned<'a, F, O>(mut inner: F) -> impl FnMut(&'a str) -> Res<&'a str, (usize, O)>
+where F: FnMut(&'a str) -> Res<&'a str, O> {
+    move |input: &'a str| {
+        let remaining = input.len();
+        let (rest, output) = inner(input)?;
+        Ok((rest, (remaining, output)))
+    }
+}
+
 pub fn skip(input: &str) -> Res<&str, &str> {
     value("", many0(alt((comment, multiline_comment, whitespace, comment_start))))(input)
 }
@@ -129,7 +141,7 @@ pub fn hexadecimal<T: Integer>(input: &str) -> Res<&str, T> {
                 )
             )
         ),
-        |out: &str| T::from_str_radix(&str::replace(&out, "_", ""), 16)
+        |out: &str| T::from_str_radix(&str::replace(out, "_", ""), 16)
     )(input)
 }
 
@@ -140,7 +152,7 @@ pub fn decimal<T: Integer>(input: &str) -> Res<&str, T> {
                 terminated(digit1, many0(char('_')))
             )
         ),
-        |out: &str| T::from_str_radix(&str::replace(&out, "_", ""), 10)
+        |out: &str| T::from_str_radix(&str::replace(out, "_", ""), 10)
     )(input)
 }
 
@@ -154,7 +166,7 @@ pub fn binary<T: Integer>(input: &str) -> Res<&str, T> {
                 )
             )
         ),
-        |out: &str| T::from_str_radix(&str::replace(&out, "_", ""), 2)
+        |out: &str| T::from_str_radix(&str::replace(out, "_", ""), 2)
     )(input)
 }
 
@@ -168,7 +180,7 @@ pub fn octal<T: Integer>(input: &str) -> Res<&str, T> {
                 )
             )
         ),
-        |out: &str| T::from_str_radix(&str::replace(&out, "_", ""), 8)
+        |out: &str| T::from_str_radix(&str::replace(out, "_", ""), 8)
     )(input)
 }
 
@@ -194,29 +206,29 @@ pub fn is_alphabetic_char(i: char) -> bool {
     is_alphabetic(i as u8)
 }
 
-pub(crate) fn ws<'a, F: 'a, O, E: ParseError<&'a str>>(inner: F) ->
+pub fn ws<'a, F, O, E: ParseError<&'a str>>(inner: F) ->
 impl FnMut(&'a str) -> IResult<&'a str, O, E>
-where F: Fn(&'a str) -> IResult<&'a str, O, E> { delimited(multispace0, inner, multispace0) }
+where F: 'a + Fn(&'a str) -> IResult<&'a str, O, E> { delimited(multispace0, inner, multispace0) }
 
-pub(crate) fn trailing_ws<'a, F: 'a, O, E: ParseError<&'a str>>(inner: F) ->
+pub fn trailing_ws<'a, F, O, E: ParseError<&'a str>>(inner: F) ->
 impl FnMut(&'a str) -> IResult<&'a str, O, E>
-where F: Fn(&'a str) -> IResult<&'a str, O, E> { terminated(inner, multispace0) }
+where F: 'a + Fn(&'a str) -> IResult<&'a str, O, E> { terminated(inner, multispace0) }
 
-pub(crate) fn leading_ws<'a, F: 'a, O, E: ParseError<&'a str>>(inner: F) ->
+pub fn leading_ws<'a, F, O, E: ParseError<&'a str>>(inner: F) ->
 impl FnMut(&'a str) -> IResult<&'a str, O, E>
-where F: Fn(&'a str) -> IResult<&'a str, O, E> { preceded(multispace0, inner) }
+where F: 'a + Fn(&'a str) -> IResult<&'a str, O, E> { preceded(multispace0, inner) }
 
-pub(crate) fn ws_nonl<'a, F: 'a, O, E: ParseError<&'a str>>(inner: F) ->
+pub fn ws_nonl<'a, F, O, E: ParseError<&'a str>>(inner: F) ->
 impl FnMut(&'a str) -> IResult<&'a str, O, E>
-where F: Fn(&'a str) -> IResult<&'a str, O, E> { delimited(spaces0_nonl, inner, spaces0_nonl) }
+where F: 'a + Fn(&'a str) -> IResult<&'a str, O, E> { delimited(spaces0_nonl, inner, spaces0_nonl) }
 
-pub(crate) fn trailing_ws_nonl<'a, F: 'a, O, E: ParseError<&'a str>>(inner: F) ->
+pub fn trailing_ws_nonl<'a, F, O, E: ParseError<&'a str>>(inner: F) ->
 impl FnMut(&'a str) -> IResult<&'a str, O, E>
-where F: Fn(&'a str) -> IResult<&'a str, O, E> { terminated(inner, spaces0_nonl) }
+where F: 'a + Fn(&'a str) -> IResult<&'a str, O, E> { terminated(inner, spaces0_nonl) }
 
-pub(crate) fn leading_ws_nonl<'a, F: 'a, O, E: ParseError<&'a str>>(inner: F) ->
+pub fn leading_ws_nonl<'a, F, O, E: ParseError<&'a str>>(inner: F) ->
 impl FnMut(&'a str) -> IResult<&'a str, O, E>
-where F: Fn(&'a str) -> IResult<&'a str, O, E> { preceded(spaces0_nonl, inner) }
+where F: 'a + Fn(&'a str) -> IResult<&'a str, O, E> { preceded(spaces0_nonl, inner) }
 
 pub fn is_condition(i: char) -> bool {
     let symbols = ['=', '!', '>', '<'];
@@ -237,6 +249,19 @@ pub fn next_token(input: &str) -> Res<&str, ()> {
     )(input)
 }
 
+/// Nothing but spaces and maybe a comment before the end of the line. A comment has to start
+/// with a space after it here, `%x` is a virtual register
+pub fn end_of_line(input: &str) -> Res<&str, ()> {
+    value(
+        (),
+        preceded(space0, alt((
+            terminated(comment_start, alt((space1, line_ending, eof))),
+            line_ending,
+            eof
+        )))
+    )(input)
+}
+
 pub fn next_instruction(input: &str) -> Res<&str, ()> {
     value(
         (),
@@ -246,10 +271,5 @@ pub fn next_instruction(input: &str) -> Res<&str, ()> {
 
 /// Check if a string can be parsed into a number
 pub fn is_number(input: &str) -> bool {
-    let res = peek(number::<i128>)(input);
-    if let Ok((_, _)) = res {
-        true
-    } else {
-        false
-    }
+    peek(number::<i128>)(input).is_ok()
 }
\ No newline at end of file
@@ -2,85 +2,201 @@ use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
 use nom::branch::alt;
-use nom::combinator::eof;
-use nom::error::{context, convert_error};
-use nom::Finish;
-use nom::multi::many0;
-use nom::sequence::{preceded, terminated};
+use nom::combinator::map;
+use nom::error::{context, convert_error, VerboseError, VerboseErrorKind};
+use nom::Err;
 use thiserror::Error;
 use crate::architecture::batpu2::instruction::Instruction;
-use crate::architecture::batpu2::opcode::Opcode;
-use crate::parser::helpers::{skip, Res};
-use crate::parser::wrappers::{parse_definitions, parse_instruction, parse_labels};
+use crate::architecture::batpu2::operand::Operand;
+use crate::architecture::batpu2::{DIRECTIVES, OPCODES};
+use crate::parser::helpers::{identifier, skip, Res};
+use crate::parser::wrappers::{parse_aliases, parse_definitions, parse_directives, parse_instruction, parse_labels};
+use crate::instructions::InstructionSet;
+use crate::source::SourceLocation;
+use crate::suggest::Suggestion;
 
 pub mod helpers;
 pub mod tokens;
 pub mod wrappers;
 
+/// Parsing a file stops after this many syntax errors
+pub const MAX_SYNTAX_ERRORS: usize = 20;
+
+/// An instruction along with its byte offset into the file
+type Located = (usize, Instruction);
+
 #[derive(Debug, Error)]
 pub enum ParseError {
     #[error("Invalid extension: {0}")]
     InvalidExtension(String),
     #[error("No instructions found in file: {0}")]
     NoInstructions(String),
-    #[error("Failed to parse file: {file}.\n{reason}")]
+    #[error("{location}: Syntax error{suggestion}\n{reason}")]
     FailedToParse {
-        file: String,
+        location: SourceLocation,
+        suggestion: Suggestion,
         reason: String
     },
+    #[error("{0}: Too many syntax errors, stopped parsing")]
+    TooManyErrors(String),
     #[error("IO Error: {0}")]
     IoError(#[from] std::io::Error),
 }
 
-pub fn parse(path: PathBuf) -> Result<Vec<Instruction>, ParseError> {
+/// Parses a file, skipping to the next line after every syntax error so they can all be reported
+/// at once (up to [`MAX_SYNTAX_ERRORS`]). Returns the instructions that did parse along with the
+/// syntax errors, only a file that cannot be read at all is an `Err`
+pub fn parse(path: PathBuf, instructions: &InstructionSet) -> Result<(Vec<Instruction>, Vec<ParseError>), ParseError> {
     info!("Parsing file: {}", path.display());
-    if path.extension().unwrap() != "asm" ||
-        path.extension().unwrap() != "as" ||
-        path.extension().unwrap() != "s" {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if !matches!(extension, "asm" | "as" | "s") {
         return Err(ParseError::InvalidExtension(path.display().to_string()));
     }
 
     let mut file = File::open(path.clone())?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
-    match parse_program(&contents).finish() {
-        Ok((_, program)) => {
-            if program.is_empty() {
-                warn!("No instructions found in file");
-                return Ok(program);
-            }
+    parse_source(&path.display().to_string(), &contents, instructions)
+}
+
+/// Parses source that did not come from a file on disk, like the modules of the standard library.
+/// `file_name` is what errors and listings show as the file
+pub fn parse_source(file_name: &str, contents: &str, instructions: &InstructionSet) -> Result<(Vec<Instruction>, Vec<ParseError>), ParseError> {
+    let (located, failures, truncated) = parse_program(contents, instructions);
 
-            for instruction in program.iter() {
-                if instruction.opcode !=  Opcode::_Label && instruction.opcode != Opcode::_Definition {
-                    return Ok(program);
-                }
+    let program: Vec<Instruction> = located
+        .into_iter()
+        .map(|(offset, mut instruction)| {
+            locate(&mut instruction, file_name, contents, offset);
+            instruction
+        })
+        .collect();
+
+    let mut errors: Vec<ParseError> = failures
+        .into_iter()
+        .map(|e| {
+            // The innermost error is where parsing actually got stuck
+            let remaining = e.errors.first().map_or(0, |(rest, _)| rest.len());
+            ParseError::FailedToParse {
+                location: SourceLocation::from_offset(file_name, contents, contents.len() - remaining),
+                suggestion: suggest_keyword(&e, instructions),
+                reason: convert_error(contents, e)
             }
-            Err(ParseError::NoInstructions(path.display().to_string()))
-        },
-        Err(e) => {
-            Err(ParseError::FailedToParse {
-                file: path.display().to_string(),
-                reason: convert_error(contents.as_str(), e)
-            })
+        })
+        .collect();
+
+    if truncated {
+        errors.push(ParseError::TooManyErrors(file_name.to_string()));
+    }
+
+    if !errors.is_empty() {
+        warn!("{} syntax errors in {}", errors.len(), file_name);
+        return Ok((program, errors));
+    }
+
+    if program.is_empty() {
+        warn!("No instructions found in file");
+        return Ok((program, errors));
+    }
+
+    for instruction in program.iter() {
+        if !instruction.opcode.is_directive() {
+            return Ok((program, errors));
+        }
+    }
+    Err(ParseError::NoInstructions(file_name.to_string()))
+}
+
+/// Turns the offsets the parser recorded into source locations, including the ones of the
+/// instructions that `.fill` and `.filler` repeat
+fn locate(instruction: &mut Instruction, file_name: &str, contents: &str, offset: usize) {
+    let offsets: Vec<usize> = instruction.operand_offsets
+        .drain(..)
+        .map(|remaining| contents.len() - remaining)
+        .collect();
+    instruction.source = SourceLocation::from_offset(file_name, contents, offset);
+    instruction.operand_sources = offsets
+        .iter()
+        .map(|at| SourceLocation::from_offset(file_name, contents, *at))
+        .collect();
+
+    for (operand, at) in instruction.operands.iter_mut().zip(offsets) {
+        if let Operand::Instr(inner) = operand {
+            locate(inner, file_name, contents, at);
         }
     }
 }
 
-fn parse_program(input: &str) -> Res<&str, Vec<Instruction>> {
-    context(
-        "Program",
-        terminated(
-            many0(
-                preceded(
-                    skip,
-                    alt((
-                        parse_labels,
-                        parse_definitions,
-                        parse_instruction
-                    ))
-                )
-            ),
-            preceded(skip, eof)
-        )
-    )(input)
+/// Parses every instruction along with its byte offset into the file. A line that fails to parse
+/// is skipped, and its error kept, until [`MAX_SYNTAX_ERRORS`] is reached. The flag is set when
+/// parsing stopped there with input left over
+fn parse_program<'a>(mut input: &'a str, instructions: &InstructionSet) -> (Vec<Located>, Vec<VerboseError<&'a str>>, bool) {
+    let total = input.len();
+    let mut program = Vec::new();
+    let mut errors = Vec::new();
+
+    loop {
+        input = match skip(input) {
+            Ok((rest, _)) => rest,
+            Err(_) => input
+        };
+        if input.is_empty() {
+            break;
+        }
+        if errors.len() >= MAX_SYNTAX_ERRORS {
+            return (program, errors, true);
+        }
+
+        match context("Program", located(total, instructions))(input) {
+            Ok((rest, instruction)) => {
+                program.push(instruction);
+                input = rest;
+            },
+            Err(Err::Error(e)) | Err(Err::Failure(e)) => {
+                errors.push(e);
+                input = next_line(input);
+            },
+            Err(Err::Incomplete(_)) => break
+        }
+    }
+    (program, errors, false)
+}
+
+/// Suggests a keyword when the parser gave up on an unknown opcode
+fn suggest_keyword(e: &VerboseError<&str>, instructions: &InstructionSet) -> Suggestion {
+    let unknown = e.errors
+        .iter()
+        .find(|(_, kind)| matches!(kind, VerboseErrorKind::Context("Opcode (Invalid)")))
+        .and_then(|(rest, _)| identifier(rest).ok());
+
+    match unknown {
+        Some((_, name)) => Suggestion::closest(name, OPCODES.iter().copied().chain(DIRECTIVES.iter().copied()).chain(instructions.names())),
+        None => Suggestion::default()
+    }
+}
+
+/// Everything after the next line break, where parsing resumes after a syntax error
+fn next_line(input: &str) -> &str {
+    match input.find('\n') {
+        Some(end) => &input[end + 1..],
+        None => ""
+    }
+}
+
+/// Attaches the byte offset of the input (`total` being the length of the whole file)
+fn located<'a>(total: usize, instructions: &InstructionSet) -> impl FnMut(&'a str) -> Res<&'a str, Located> + '_ {
+    move |input: &'a str| {
+        let offset = total - input.len();
+        map(|i| parse_line(i, instructions), move |instruction| (offset, instruction))(input)
+    }
+}
+
+fn parse_line<'a>(input: &'a str, instructions: &InstructionSet) -> Res<&'a str, Instruction> {
+    alt((
+        |i| parse_directives(i, instructions),
+        parse_labels,
+        parse_definitions,
+        parse_aliases,
+        |i| parse_instruction(i, instructions)
+    ))(input)
 }
\ No newline at end of file
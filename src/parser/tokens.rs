@@ -9,11 +9,15 @@ use nom::{
     bytes::complete::{
         tag,
         tag_no_case,
+        take_while,
         take_while1
     },
     character::complete::{
+        char,
+        alpha1,
         digit1,
         one_of,
+        space0,
         space1,
         multispace1
     },
@@ -22,22 +26,34 @@ use nom::{
         eof,
         opt,
         recognize,
-        fail
+        fail,
+        map,
+        not,
+        value
+    },
+    multi::{
+        many1,
+        fold_many0
     },
     sequence::{
         delimited,
-        pair
-    },
-    IResult
+        pair,
+        preceded,
+        separated_pair,
+        terminated
+    }
 };
 use crate::architecture::batpu2::opcode::Opcode;
 use crate::architecture::batpu2::operand::{
+    alias::RegisterAlias,
+    pair::RegisterPair,
     immediate::*,
     label::Label,
     port::Port,
     register::Register,
-    condition::Condition,
-    definition::Definition,
+    condition::{Comparison, Condition},
+    definition::{Definition, DefinitionKind},
+    expression::{Node, Operator, UnaryOperator},
 };
 use crate::parser::helpers::*;
 
@@ -73,28 +89,108 @@ pub fn port(input: &str) -> Res<&str, Port> {
     }
 }
 
+/// `define NAME value`, or `define NAME:kind value` to restrict where it can be used
 pub fn define(input: &str) -> Res<&str, Definition> {
     let (rest, _declaration) = tag_no_case("define")(input)?;
     let (rest, _) = cut(space1)(rest)?;
     let (rest, name) = cut(identifier)(rest)?;
+    let (rest, kind) = opt(preceded(tag(":"), cut(definition_kind)))(rest)?;
     let (rest, _) = cut(space1)(rest)?;
     trace!("Skipped whitespace after definition name");
-    let (rest, signed) = opt(one_of("+-"))(rest)?;
-    let negative: bool = signed.unwrap_or('+') == '-';
-    let (rest, imm) = cut(number::<i16>)(rest)?;
-    let value = if negative {-imm} else {imm};
+    let (rest, value) = cut(|i| definition_value(i, kind))(rest)?;
 
     trace!("Found definition value: {}", value);
-    match Definition::new_def(name, value) {
+    match Definition::new_def(name, value, kind) {
         Some(d) => Ok((rest, d)),
+        None if Definition::new_opr(name).is_none() => {
+            cut(context("Definition (Invalid name)", fail))(input)
+        },
         None => {
-            context("Definition (Invalid name)", fail)(input)
+            cut(context("Definition (Value out of range)", fail))(input)
         }
     }
 }
 
-pub fn definition(input: &str) -> Res<&str, Definition> {
+fn definition_kind(input: &str) -> Res<&str, DefinitionKind> {
+    let (rest, kind) = identifier(input)?;
+    match DefinitionKind::from_str(kind) {
+        Ok(k) => Ok((rest, k)),
+        Err(_) => {
+            context("Definition (Expected imm, offset, port, addr or reg)", fail)(input)
+        }
+    }
+}
+
+/// Registers are written as registers and ports may be written by name, everything else is a number
+fn definition_value(input: &str, kind: Option<DefinitionKind>) -> Res<&str, i16> {
+    let signed = |input| {
+        let (rest, signed) = opt(one_of("+-"))(input)?;
+        let negative: bool = signed.unwrap_or('+') == '-';
+        let (rest, imm) = number::<i16>(rest)?;
+        Ok((rest, if negative {-imm} else {imm}))
+    };
+
+    match kind {
+        Some(DefinitionKind::Register) => map(register, |r| r as i16)(input),
+        Some(DefinitionKind::Port) => alt((map(port, |p| p as i16), signed))(input),
+        _ => signed(input)
+    }
+}
+
+pub fn alias(input: &str) -> Res<&str, RegisterAlias> {
+    let (rest, _declaration) = tag_no_case("alias")(input)?;
+    let (rest, _) = cut(space1)(rest)?;
+    let (rest, local) = opt(terminated(tag_no_case("local"), space1))(rest)?;
+    let (rest, name) = cut(identifier)(rest)?;
+    let (rest, _) = cut(space1)(rest)?;
+    let (rest, reg) = cut(register)(rest)?;
+
+    trace!("Found alias: {} = {}", name, reg);
+    match RegisterAlias::new_def(name, reg, local.is_some()) {
+        Some(a) => Ok((rest, a)),
+        None => {
+            context("Alias (Invalid name)", fail)(input)
+        }
+    }
+}
+
+/// An alias name, or a virtual register like `%count`
+pub fn alias_usage(input: &str) -> Res<&str, RegisterAlias> {
+    if let Ok((rest, alias)) = virtual_register(input) {
+        return Ok((rest, alias));
+    }
     let (rest, name) = identifier(input)?;
+    match RegisterAlias::new_opr(name) {
+        Some(alias) => Ok((rest, alias)),
+        None => {
+            context("Alias (Invalid name)", fail)(input)
+        }
+    }
+}
+
+pub fn virtual_register(input: &str) -> Res<&str, RegisterAlias> {
+    let (rest, name) = preceded(char('%'), identifier)(input)?;
+    Ok((rest, RegisterAlias::new_virtual(name)))
+}
+
+/// A pair name, or two registers written as `high:low`
+pub fn register_pair(input: &str) -> Res<&str, RegisterPair> {
+    if let Ok((rest, (high, low))) = separated_pair(register, char(':'), register)(input) {
+        return Ok((rest, RegisterPair::new_literal(high, low)));
+    }
+
+    let (rest, name) = identifier(input)?;
+    match RegisterPair::new_opr(name) {
+        Some(pair) => Ok((rest, pair)),
+        None => {
+            context("Pair (Invalid name)", fail)(input)
+        }
+    }
+}
+
+/// A definition name, struct fields are written as `struct.field`
+pub fn definition(input: &str) -> Res<&str, Definition> {
+    let (rest, name) = recognize(pair(identifier, opt(pair(tag("."), identifier))))(input)?;
     match Definition::new_opr(name) {
         Some(def) => Ok((rest, def)),
         None => {
@@ -113,12 +209,152 @@ pub fn condition(input: &str) -> Res<&str, Condition> {
     }
 }
 
+/// `==`, `!=`, `<`, `>=`, `>` or `<=`, or the same as `eq`, `ne`, `lt`, `ge`, `gt` or `le`
+pub fn comparison(input: &str) -> Res<&str, Comparison> {
+    let (rest, comparison) = alt((take_while1(|c| "=!<>".contains(c)), alpha1))(input)?;
+    match Comparison::from_str(comparison) {
+        Ok(c) => Ok((rest, c)),
+        Err(_) => {
+            cut(context("Comparison (Expected ==, !=, <, >=, > or <=)", fail))(input)
+        }
+    }
+}
+
+/// A label reference can be global (`.draw`), fully qualified local (`.draw.loop`),
+/// local to the current scope (`..loop`) or anonymous (`.+`, `.--`, ...)
 pub fn label_usage(input: &str) -> Res<&str, Label> {
     let (rest, _) = context("Label Usage", tag("."))(input)?;
-    let (rest, name) = identifier(rest)?;
+    let (rest, name) = alt((
+        local_label_name,
+        recognize(pair(identifier, opt(local_label_name))),
+        anonymous_label_usage
+    ))(rest)?;
+    if Opcode::from_directive(name).is_some_and(|d| d.is_bare_directive()) {
+        return cut(context("Label (Reserved directive name, .else and the like cannot be labels)", fail))(input);
+    }
     Ok((rest, Label::new(name.to_string())))
 }
 
+/// `.loop` in `..loop`, scoped to the preceding global label
+fn local_label_name(input: &str) -> Res<&str, &str> {
+    recognize(pair(tag("."), identifier))(input)
+}
+
+fn anonymous_label_usage(input: &str) -> Res<&str, &str> {
+    alt((
+        recognize(many1(char('+'))),
+        recognize(many1(char('-')))
+    ))(input)
+}
+
+/// Operators from the lowest to the highest precedence, along with the characters that
+/// must not follow them (so `<` does not eat `<<` and `-` does not eat a `--` comment)
+const OPERATORS: [&[(&str, &str, Operator)]; 7] = [
+    &[("|", "|", Operator::Or)],
+    &[("^", "", Operator::Xor)],
+    &[("&", "&", Operator::And)],
+    &[("==", "", Operator::Eq), ("!=", "", Operator::Ne)],
+    &[("<=", "", Operator::Le), (">=", "", Operator::Ge), ("<", "<=", Operator::Lt), (">", ">=", Operator::Gt)],
+    &[("<<", "", Operator::Shl), (">>", "", Operator::Shr)],
+    &[("+", "", Operator::Add), ("-", "-", Operator::Sub)],
+];
+
+const MUL_OPERATORS: &[(&str, &str, Operator)] = &[("*", "", Operator::Mul), ("/", "/*", Operator::Div)];
+
+/// Expressions over `$`, labels, definitions, ports and numbers with C-like precedence,
+/// e.g. `.table + 2`, `$ + 4` or `ROW_COUNT * 2 < 16`
+pub fn expression(input: &str) -> Res<&str, Node> {
+    binary_expression(input, 0)
+}
+
+fn binary_expression(input: &str, level: usize) -> Res<&str, Node> {
+    let operators = if level < OPERATORS.len() { OPERATORS[level] } else { MUL_OPERATORS };
+    let operand = |i| {
+        if level < OPERATORS.len() {
+            binary_expression(i, level + 1)
+        } else {
+            unary_expression(i)
+        }
+    };
+
+    let (rest, first) = operand(input)?;
+    fold_many0(
+        pair(
+            delimited(space0, |i| operator(i, operators), space0),
+            operand
+        ),
+        move || first.clone(),
+        |left, (op, right)| Node::Binary(Box::new(left), op, Box::new(right))
+    )(rest)
+}
+
+fn operator<'a>(input: &'a str, operators: &[(&str, &str, Operator)]) -> Res<&'a str, Operator> {
+    for (text, not_followed_by, op) in operators {
+        if let Some(rest) = input.strip_prefix(text) {
+            if !rest.starts_with(|c| not_followed_by.contains(c)) {
+                return Ok((rest, *op));
+            }
+        }
+    }
+    context("Operator", fail)(input)
+}
+
+fn unary_expression(input: &str) -> Res<&str, Node> {
+    alt((
+        map(
+            preceded(terminated(char('-'), not(char('-'))), unary_expression),
+            |n| Node::Unary(UnaryOperator::Negate, Box::new(n))
+        ),
+        map(
+            preceded(char('~'), unary_expression),
+            |n| Node::Unary(UnaryOperator::Not, Box::new(n))
+        ),
+        primary_expression
+    ))(input)
+}
+
+fn primary_expression(input: &str) -> Res<&str, Node> {
+    alt((
+        delimited(
+            pair(char('('), space0),
+            expression,
+            pair(space0, cut(char(')')))
+        ),
+        value(Node::Here, tag("$")),
+        map(label_usage, Node::Label),
+        map(number::<i32>, Node::Number),
+        map(port, |p| Node::Number(p as i32)),
+        map(definition, Node::Def)
+    ))(input)
+}
+
+/// A double-quoted string on a single line, e.g. `"GAME OVER"`
+pub fn string(input: &str) -> Res<&str, &str> {
+    context(
+        "String",
+        delimited(
+            char('"'),
+            take_while(|c| c != '"' && c != '\n' && c != '\r'),
+            cut(char('"'))
+        )
+    )(input)
+}
+
+/// A directive name such as `.assert`, labels with other names are left alone. So is a label
+/// named after a directive that takes operands, like `.print`, when nothing follows it
+pub fn directive(input: &str) -> Res<&str, Opcode> {
+    let (rest, name) = preceded(tag("."), identifier)(input)?;
+    match Opcode::from_directive(name) {
+        Some(d) if !d.is_bare_directive() && end_of_line(rest).is_ok() => {
+            context("Directive (Label with the same name)", fail)(input)
+        },
+        Some(d) => Ok((rest, d)),
+        None => {
+            context("Directive (Unknown)", fail)(input)
+        }
+    }
+}
+
 pub fn offset(input: &str) -> Res<&str, Offset> {
     let (rest, signed) = opt(one_of("+-"))(input)?;
     let negative: bool = signed.unwrap_or('+') == '-';
@@ -160,7 +396,7 @@ pub fn immediate(input: &str) -> Res<&str, Immediate> {
 }
 
 pub fn register(input: &str) -> Res<&str, Register> {
-    let (rest, reg) = cut(context(
+    let (rest, reg) = context(
         "Register (Expected register here)",
         recognize(
             pair(
@@ -168,7 +404,7 @@ pub fn register(input: &str) -> Res<&str, Register> {
                 digit1
             )
         )
-    ))(input)?;
+    )(input)?;
 
     let reg = Register::from_str(reg);
 
@@ -199,7 +435,7 @@ pub fn label_define(input: &str) -> Res<&str, &str> {
         "Label",
         delimited(
             tag("."),
-            identifier,
+            alt((local_label_name, identifier, tag("+"), tag("-"))),
             alt((eof, multispace1))
         )
     )(input)
@@ -1,14 +1,24 @@
+use std::str::FromStr;
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    combinator::{cut, fail, map_res, opt, peek},
-    error::{context, VerboseError}
+    character::complete::{multispace0, space0, space1},
+    multi::separated_list1,
+    combinator::{cut, fail, map, map_res, opt, peek, verify},
+    error::{context, VerboseError},
+    sequence::{pair, preceded, tuple}
 };
 use crate::architecture::batpu2::instruction::Instruction;
-use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::opcode::{Field, Opcode};
 use crate::architecture::batpu2::operand::Operand;
-use crate::architecture::batpu2::operand::register::Register;
+use crate::architecture::batpu2::operand::definition::Definition;
+use crate::architecture::batpu2::operand::pair::RegisterPair;
+use crate::architecture::batpu2::operand::expression::{Expression, Node};
+use crate::architecture::batpu2::operand::immediate::Immediate;
+use crate::architecture::batpu2::operand::port::Port;
+use crate::instructions::{InstructionAlias, InstructionSet};
 use crate::parser::helpers::*;
+use crate::sprite::SCREEN_SIZE;
 use crate::parser::tokens::*;
 
 fn no_operands(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
@@ -19,281 +29,679 @@ fn no_operands(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
     }))
 }
 
-fn three_operands(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+fn one_operand(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
     let (rest, _) = next_token(input)?;
-    let (rest, a) = register(rest)?;
-    let (rest, _) = next_token(rest)?;
-    let (rest, b) = register(rest)?;
-    let (rest, _) = next_token(rest)?;
-    let (rest, c) = register(rest)?;
+    let (rest, (a_at, a)) = spanned(operand_register)(rest)?;
     let (rest, _) = next_instruction(rest)?;
     Ok((rest, {
-        trace!("Found instruction: {} {}, {}, {}", opcode, a, b, c);
+        trace!("Found instruction: {} {}", opcode, a);
         let mut temp = Instruction::new(opcode);
-        temp.add_register(a);
-        temp.add_register(b);
-        temp.add_register(c);
+        temp.add_operand(a);
+        temp.operand_offsets = vec![a_at];
         temp
     }))
 }
 
-fn two_operands(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+/// `{SUBI|ANDI|XORI} a, imm, c?` and `CMPI a, imm`, the result goes to `a` unless `c` is given.
+/// The expander turns them into an `LDI` into the scratch register and the real operation
+fn immediate_alu_instructions(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    use Operand as O;
     let (rest, _) = next_token(input)?;
-    let (rest, a) = register(rest)?;
+    let (rest, (a_at, a)) = spanned(operand_register)(rest)?;
     let (rest, _) = next_token(rest)?;
-    let (rest, b) = register(rest)?;
+    let (rest, (b_at, b)) = spanned(operand_immediate)(rest)?;
+    let (rest, c) = match opcode {
+        Opcode::CMPI => (rest, None),
+        _ => opt(preceded(
+            pair(space0, opt(tag(","))),
+            preceded(space0, spanned(alt((map(register, O::Reg), map(alias_usage, O::Alias)))))
+        ))(rest)?
+    };
     let (rest, _) = next_instruction(rest)?;
     Ok((rest, {
         trace!("Found instruction: {} {}, {}", opcode, a, b);
         let mut temp = Instruction::new(opcode);
-        temp.add_register(a);
-        temp.add_register(b);
+        temp.add_operand(a);
+        temp.add_operand(b);
+        temp.operand_offsets = vec![a_at, b_at];
+        if let Some((c_at, c)) = c {
+            temp.add_operand(c);
+            temp.operand_offsets.push(c_at);
+        }
         temp
     }))
 }
 
-fn one_operand(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+/// `{MUL|DIV|MOD} a, b, c?`, the result goes to `a` unless `c` is given. `b` is a register or a
+/// constant, a name is a definition here until the alias binder finds an alias with that name
+fn arithmetic_instructions(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    use Operand as O;
     let (rest, _) = next_token(input)?;
-    let (rest, a) = register(rest)?;
+    let (rest, (a_at, a)) = spanned(operand_register)(rest)?;
+    let (rest, _) = next_token(rest)?;
+    let (rest, (b_at, b)) = spanned(alt((map(register, O::Reg), map(virtual_register, O::Alias), operand_immediate)))(rest)?;
+    let (rest, c) = opt(preceded(
+        pair(space0, opt(tag(","))),
+        preceded(space0, spanned(alt((map(register, O::Reg), map(alias_usage, O::Alias)))))
+    ))(rest)?;
     let (rest, _) = next_instruction(rest)?;
     Ok((rest, {
-        trace!("Found instruction: {} {}", opcode, a);
+        trace!("Found instruction: {} {}, {}", opcode, a, b);
         let mut temp = Instruction::new(opcode);
-        temp.add_register(a);
+        temp.add_operand(a);
+        temp.add_operand(b);
+        temp.operand_offsets = vec![a_at, b_at];
+        if let Some((c_at, c)) = c {
+            temp.add_operand(c);
+            temp.operand_offsets.push(c_at);
+        }
         temp
     }))
 }
 
-fn immediate_instructions(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
-    let (rest, _) = next_token(input)?;
-    let (rest, a) = register(rest)?;
-    let (rest, _) = next_token(rest)?;
-    let (rest, b) = operand_immediate(rest)?;
+/// `{PUSHALL|POPALL} a, b, ...`, without registers every register but r0 and the stack pointer
+fn register_list_instructions(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    use Operand as O;
+    let (rest, registers) = opt(preceded(
+        space1,
+        separated_list1(
+            pair(space0, tag(",")),
+            preceded(space0, spanned(alt((map(register, O::Reg), map(alias_usage, O::Alias)))))
+        )
+    ))(input)?;
     let (rest, _) = next_instruction(rest)?;
-    match b {
-        Operand::Imm(imm) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}, {}", opcode, a, imm);
-                let mut temp = Instruction::new(opcode);
-                temp.add_register(a);
-                temp.add_immediate(imm);
-                temp
-            }))
-        }
-        Operand::Def(def) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}, {}", opcode, a, def);
-                let mut temp = Instruction::new(opcode);
-                temp.add_register(a);
-                temp.add_definition(def);
-                temp
-            }))
-        }
-        Operand::Port(p) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}, {}", opcode, a, p);
-                let mut temp = Instruction::new(opcode);
-                temp.add_register(a);
-                temp.add_port(p);
-                temp
-            }))
+    Ok((rest, {
+        trace!("Found instruction: {} {:?}", opcode, registers);
+        let mut temp = Instruction::new(opcode);
+        for (register_at, register) in registers.unwrap_or_default() {
+            temp.add_operand(register);
+            temp.operand_offsets.push(register_at);
         }
-        Operand::Char(ch) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}, {}", opcode, a, ch);
-                let mut temp = Instruction::new(opcode);
-                temp.add_register(a);
-                temp.add_character(ch);
-                temp
-            }))
+        temp
+    }))
+}
+
+/// 16-bit operations on register pairs: `LDI16 a, value`, `{ADD16|SUB16} a, b, c?` (the result
+/// goes to `a` unless `c` is given), `CMP16 a, b` and `INC16 a`
+fn wide_instructions(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    let operand_pair = |i| cut(context("Pair (Expected a register pair or a pair name here)", map(register_pair, Operand::Pair)))(i);
+    let (rest, _) = next_token(input)?;
+    let (rest, (a_at, a)) = spanned(operand_pair)(rest)?;
+    let mut temp = Instruction::new(opcode);
+    temp.add_operand(a);
+    temp.operand_offsets.push(a_at);
+
+    let rest = match opcode {
+        Opcode::INC16 => rest,
+        Opcode::LDI16 => {
+            let (rest, _) = next_token(rest)?;
+            let (rest, (value_at, value)) = spanned(cut(context(
+                "Value (Expected a 16-bit value or expression)",
+                expression
+            )))(rest)?;
+            temp.add_expression(Expression::new(value));
+            temp.operand_offsets.push(value_at);
+            rest
         },
         _ => {
-            error!("Error: Unexpected operand for {opcode}: {b:?}");
-            context("Instruction (Unexpected Operand)", fail)(rest)
-        },
+            let (rest, _) = next_token(rest)?;
+            let (rest, (b_at, b)) = spanned(operand_pair)(rest)?;
+            temp.add_operand(b);
+            temp.operand_offsets.push(b_at);
+            let (rest, c) = match opcode {
+                Opcode::CMP16 => (rest, None),
+                _ => opt(preceded(
+                    pair(space0, opt(tag(","))),
+                    preceded(space0, spanned(map(register_pair, Operand::Pair)))
+                ))(rest)?
+            };
+            if let Some((c_at, c)) = c {
+                temp.add_operand(c);
+                temp.operand_offsets.push(c_at);
+            }
+            rest
+        }
+    };
+    let (rest, _) = next_instruction(rest)?;
+    trace!("Found instruction: {}", temp);
+    Ok((rest, temp))
+}
+
+/// The operand that goes in `field` of an instruction
+fn operand_for(field: Field) -> fn(&str) -> Res<&str, Operand> {
+    match field {
+        Field::A(_) | Field::B(_) | Field::C(_) => operand_register,
+        Field::Immediate => operand_immediate,
+        Field::Condition => operand_condition,
+        Field::Address => operand_address,
+        Field::Offset => operand_offset
     }
 }
 
-fn address_instructions(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
-    let (rest, _) = next_token(input)?;
-    trace!("skipped ahead: <{:?}...>", rest.chars().take(20).collect::<String>());
-    let (rest, a) = operand_address(rest)?;
-    trace!("parsed operand: <{:?}...>", rest.chars().take(20).collect::<String>());
+/// One operand for each of `fields`. An offset at the end can be left out, unless a comma says
+/// it is there
+fn field_operands<'a>(input: &'a str, fields: &[Field]) -> Res<&'a str, (Vec<Operand>, Vec<usize>)> {
+    let mut rest = input;
+    let mut operands = Vec::with_capacity(fields.len());
+    let mut offsets = Vec::with_capacity(fields.len());
+    for (i, field) in fields.iter().enumerate() {
+        let (_, comma) = opt(peek(tag(",")))(rest)?;
+        let (after, operand) = match (field, comma) {
+            (Field::Offset, None) if i + 1 == fields.len() => opt(preceded(next_token, spanned(operand_offset)))(rest)?,
+            _ => map(preceded(next_token, spanned(operand_for(*field))), Some)(rest)?
+        };
+        if let Some((at, operand)) = operand {
+            operands.push(operand);
+            offsets.push(at);
+        }
+        rest = after;
+    }
     let (rest, _) = next_instruction(rest)?;
-    trace!("skipped to next instruction: <{:?}...>", rest.chars().take(20).collect::<String>());
-    match a {
-        Operand::Label(label) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}", opcode, label);
-                let mut temp = Instruction::new(opcode);
-                temp.add_label(label);
-                temp
-            }))
+    Ok((rest, (operands, offsets)))
+}
+
+/// A real instruction, with the operands `Opcode::fields` gives it
+fn instruction<'a>(input: &'a str, opcode: Opcode, fields: &[Field]) -> Res<&'a str, Instruction> {
+    let (rest, (operands, offsets)) = field_operands(input, fields)?;
+    Ok((rest, {
+        let mut temp = Instruction::new(opcode);
+        for operand in operands {
+            temp.add_operand(operand);
         }
-        Operand::Addr(addr) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}", opcode, addr);
-                let mut temp = Instruction::new(opcode);
-                temp.add_address(addr);
-                temp
-            }))
+        temp.operand_offsets = offsets;
+        trace!("Found instruction: {}", temp);
+        temp
+    }))
+}
+
+/// An alias from the project's config has no opcode of its own, so it becomes what it stands
+/// for right away. Operands that are not parameters point at the alias itself
+fn project_alias<'a>(input: &'a str, alias: &InstructionAlias, at: usize) -> Res<&'a str, Instruction> {
+    let (rest, (operands, offsets)) = field_operands(input, &alias.fields())?;
+    Ok((rest, {
+        let mut temp = alias.expand(&operands);
+        trace!("Found instruction: {}, which is {}", alias.name, temp);
+        temp.operand_offsets = (0..temp.operands.len())
+            .map(|i| alias.parameter_of(i).and_then(|p| offsets.get(p).copied()).unwrap_or(at))
+            .collect();
+        temp
+    }))
+}
+
+pub fn parse_instruction<'a>(input: &'a str, instructions: &InstructionSet) -> Res<&'a str, Instruction> {
+    trace!("parse_instruction current input: <{:?}...>", input.chars().take(20).collect::<String>());
+    if let Ok((rest, (at, name))) = preceded(multispace0, spanned(identifier))(input) {
+        if let Some(alias) = instructions.find(name) {
+            return project_alias(rest, alias, at);
+        }
+        // Built-in aliases without an opcode of their own, like `BNE`, are expanded the same way.
+        // Only the listing still shows the mnemonic
+        if let (Some(alias), Err(_)) = (InstructionSet::builtin(name), Opcode::from_str(name)) {
+            let (rest, mut temp) = project_alias(rest, alias, at)?;
+            temp.mnemonic = Some(alias.name.clone());
+            return Ok((rest, temp));
         }
+    }
+    let (rest, opcode) = leading_ws(opcode)(input)?;
+    trace!("{} parsed, remaining: <{:?}...>", opcode, rest.chars().take(20).collect::<String>());
+    if let Some(fields) = opcode.fields() {
+        return instruction(rest, opcode, fields);
+    }
+    // Built-in pseudo-instructions like `MOV` keep their opcode until they are encoded
+    if let Some(alias) = InstructionSet::builtin(&opcode.to_string()) {
+        return instruction(rest, opcode, &alias.fields());
+    }
+    match opcode {
+        Opcode::CMPI | Opcode::SUBI | Opcode::ANDI | Opcode::XORI => immediate_alu_instructions(rest, opcode),
+        Opcode::MUL | Opcode::DIV | Opcode::MOD => arithmetic_instructions(rest, opcode),
+        Opcode::PUSH | Opcode::POP => one_operand(rest, opcode),
+        Opcode::PUSHALL | Opcode::POPALL => register_list_instructions(rest, opcode),
+        Opcode::LDI16 | Opcode::ADD16 | Opcode::SUB16 | Opcode::INC16 | Opcode::CMP16 => wide_instructions(rest, opcode),
         _ => {
-            error!("Error: Unexpected operand for {opcode}: {a:?}");
-            context("Instruction (Unexpected Operand)", fail)(rest)
-        },
+            error!("Error: Invalid opcode (How the fuck?)");
+            cut(context("Instruction (Invalid Opcode)", fail))(rest)
+        }
     }
 }
 
-fn branch_instruction(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
-    let (rest, _) = next_token(input)?;
-    let (rest, a) = condition(rest)?;
-    let (rest, _) = next_token(rest)?;
-    let (rest, b) = operand_address(rest)?;
+pub fn parse_labels(input: &str) -> Res<&str, Instruction> {
+    trace!("attempting to parse label: <{:?}>", input.chars().take(20).collect::<String>());
+    let (rest, label) = label_define(input)?;
+    Ok((rest, {
+        trace!("Found label: {}", label);
+        let mut temp = Instruction::new(Opcode::_Label);
+        temp.add_label_name(label.to_string());
+        temp
+    }))
+}
+
+pub fn parse_directives<'a>(input: &'a str, instructions: &InstructionSet) -> Res<&'a str, Instruction> {
+    trace!("attempting to parse directive: <{:?}>", input.chars().take(40).collect::<String>());
+    let (rest, directive) = directive(input)?;
+    match directive {
+        Opcode::_Assert => assert_directive(rest),
+        Opcode::_Error | Opcode::_Warning | Opcode::_Print | Opcode::_Include => message_directive(rest, directive),
+        Opcode::_PrintNumber | Opcode::_Scratch | Opcode::_PortBase => register_directive(rest, directive),
+        Opcode::_Global | Opcode::_Extern => names_directive(rest, directive),
+        Opcode::_Var | Opcode::_Array | Opcode::_Field => variable_directive(rest, directive),
+        Opcode::_Struct => struct_directive(rest),
+        Opcode::_Table => table_directive(rest),
+        Opcode::_Org => origin_directive(rest),
+        Opcode::_Align | Opcode::_Fill => count_directive(rest, directive, instructions),
+        Opcode::_Filler => filler_directive(rest, instructions),
+        Opcode::_Stack => stack_directive(rest),
+        Opcode::_Pair => pair_directive(rest),
+        Opcode::_Sprite => sprite_directive(rest),
+        Opcode::_If | Opcode::_While => condition_directive(rest, directive),
+        Opcode::_Loop => loop_directive(rest),
+        Opcode::_EndStruct | Opcode::_InitTables => no_operands(rest, directive),
+        Opcode::_Else | Opcode::_EndIf | Opcode::_EndWhile | Opcode::_EndLoop => no_operands(rest, directive),
+        _ => {
+            error!("Error: Invalid directive {directive}");
+            cut(context("Directive (Invalid)", fail))(rest)
+        }
+    }
+}
+
+fn assert_directive(input: &str) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (expr_at, expr)) = spanned(cut(context("Assertion (Expected expression)", expression)))(rest)?;
+    let (rest, message) = opt(preceded(next_token, spanned(string)))(rest)?;
     let (rest, _) = next_instruction(rest)?;
-    match b {
-        Operand::Label(label) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}, {}", opcode, a, label);
-                let mut temp = Instruction::new(opcode);
-                temp.add_condition(a);
-                temp.add_label(label);
-                temp
-            }))
+    Ok((rest, {
+        let expr = Expression::new(expr);
+        trace!("Found assertion: {}", expr);
+        let mut temp = Instruction::new(Opcode::_Assert);
+        temp.add_expression(expr);
+        temp.operand_offsets.push(expr_at);
+        match message {
+            Some((message_at, message)) => {
+                temp.add_text(message.to_string());
+                temp.operand_offsets.push(message_at);
+            },
+            None => temp.add_text("Assertion failed".to_string())
         }
-        Operand::Addr(addr) => {
-            Ok((rest, {
-                trace!("Found instruction: {} {}, {}", opcode, a, addr);
-                let mut temp = Instruction::new(opcode);
-                temp.add_condition(a);
-                temp.add_address(addr);
-                temp
-            }))
+        temp
+    }))
+}
+
+fn message_directive(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (message_at, message)) = spanned(cut(string))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found instruction: {} \"{}\"", opcode, message);
+        let mut temp = Instruction::new(opcode);
+        temp.add_text(message.to_string());
+        temp.operand_offsets = vec![message_at];
+        temp
+    }))
+}
+
+fn register_directive(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (a_at, a)) = spanned(operand_register)(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found instruction: {} {}", opcode, a);
+        let mut temp = Instruction::new(opcode);
+        temp.add_operand(a);
+        temp.operand_offsets = vec![a_at];
+        temp
+    }))
+}
+
+/// A list of label or definition names, the `.` in front of labels is optional
+fn names_directive(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, names) = cut(separated_list1(
+        pair(space0, tag(",")),
+        preceded(pair(space0, opt(tag("."))), spanned(identifier))
+    ))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found instruction: {} {:?}", opcode, names);
+        let mut temp = Instruction::new(opcode);
+        for (name_at, name) in names {
+            temp.add_label_name(name.to_string());
+            temp.operand_offsets.push(name_at);
         }
-        _ => {
-            error!("Error: Unexpected operand for {opcode}");
-            context("Instruction (Unexpected Operand)", fail)(rest)
+        temp
+    }))
+}
+
+/// `.var name`, `.array name length` or `.field name length?`, the address (or offset)
+/// is filled in by the allocator
+fn variable_directive(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (name_at, def)) = spanned(cut(context("Variable (Invalid name)", definition)))(rest)?;
+    let (rest, length) = match opcode {
+        Opcode::_Field => {
+            let (rest, length) = opt(preceded(
+                next_token,
+                spanned(verify(number::<u8>, |n| (1..=Port::PixelX as u8).contains(n)))
+            ))(rest)?;
+            (rest, length)
         },
-    }
+        Opcode::_Array => {
+            let (rest, _) = next_token(rest)?;
+            let (rest, length) = spanned(cut(context(
+                "Array (Expected a length from 1 to 240)",
+                verify(number::<u8>, |n| (1..=Port::PixelX as u8).contains(n))
+            )))(rest)?;
+            (rest, Some(length))
+        },
+        _ => (rest, None)
+    };
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found instruction: {} {}", opcode, def.name);
+        let mut temp = Instruction::new(opcode);
+        temp.add_definition(def);
+        temp.operand_offsets.push(name_at);
+        if let Some((length_at, length)) = length {
+            temp.add_immediate(Immediate::new(length as i16).unwrap());
+            temp.operand_offsets.push(length_at);
+        }
+        temp
+    }))
 }
 
-/// You put a comma, therefore, there must be a next value
-fn there_must_be_a_next_value(input: &str, opcode: Opcode, a: Register, b: Register) -> Res<&str, Instruction> {
-    let (rest, _) = next_token(input)?;
-    let (rest, offset_value) = operand_offset(rest)?;
+fn struct_directive(input: &str) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (name_at, name)) = spanned(cut(context("Struct (Invalid name)", identifier)))(rest)?;
     let (rest, _) = next_instruction(rest)?;
-    there_is_a_next_value(rest, opcode, a, b, offset_value)
-}
-
-/// You didn't put a comma, but there could still be a next value
-fn there_could_be_a_next_value(input: &str, opcode: Opcode, a: Register, b: Register) -> Res<&str, Instruction> {
-    let (rest, test) = opt(next_token)(input)?;
-    match test {
-        Some(_) => {
-            trace!("skipped ahead: <{:?}...>", rest.chars().take(20).collect::<String>());
-            let (rest, oper) = opt(operand_offset)(rest)?;
-            let (rest, _) = next_instruction(rest)?;
-            match oper {
-                Some(o) => there_is_a_next_value(rest, opcode, a, b, o),
-                None => there_is_not_a_next_value(rest, opcode, a, b),
+    Ok((rest, {
+        trace!("Found struct: {}", name);
+        let mut temp = Instruction::new(Opcode::_Struct);
+        temp.add_label_name(name.to_string());
+        temp.operand_offsets.push(name_at);
+        temp
+    }))
+}
+
+/// `.table name value, ...` where every value is a byte, a character, a string (one byte per
+/// character) or an expression. The values are written to data memory by `.init_tables`
+fn table_directive(input: &str) -> Res<&str, Instruction> {
+    // Without a name `.table` is a plain label, which older programs use
+    let (rest, _) = space1(input)?;
+    let (rest, (name_at, def)) = spanned(cut(context("Table (Invalid name)", definition)))(rest)?;
+    let (rest, _) = next_token(rest)?;
+    let (rest, values) = cut(separated_list1(
+        pair(space0, tag(",")),
+        preceded(space0, spanned(table_value))
+    ))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found table: {}", def.name);
+        let mut temp = Instruction::new(Opcode::_Table);
+        temp.add_definition(def);
+        temp.operand_offsets.push(name_at);
+        for (value_at, value) in values {
+            for operand in value {
+                temp.add_operand(operand);
+                temp.operand_offsets.push(value_at);
             }
         }
-        None => there_is_not_a_next_value(rest, opcode, a, b)
+        temp
+    }))
+}
+
+fn table_value(input: &str) -> Res<&str, Vec<Operand>> {
+    use Operand as O;
+    // An unterminated string is an error, anything else that is not a string is tried below
+    match string(input) {
+        Ok((rest, text)) => return Ok((rest, text.chars().map(O::Char).collect())),
+        Err(nom::Err::Error(_)) => {},
+        Err(e) => return Err(e)
+    }
+    if let Ok((rest, c)) = character(input) {
+        return Ok((rest, vec![O::Char(c)]));
+    }
+
+    let (rest, node) = context("Table (Expected a byte, character, string or expression)", expression)(input)?;
+    match node {
+        Node::Number(n) => match i16::try_from(n).ok().and_then(Immediate::new) {
+            Some(imm) => Ok((rest, vec![O::Imm(imm)])),
+            None => cut(context("Table (Value out of range)", fail))(input)
+        },
+        Node::Def(def) => Ok((rest, vec![O::Def(def)])),
+        node => Ok((rest, vec![O::Expr(Expression::new(node))]))
     }
 }
 
-/// The most deeply nested piece of shit I've ever written
-fn load_and_store(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
-    let (rest, _) = next_token(input)?;
-    trace!("skipped ahead: <{:?}...>", rest.chars().take(20).collect::<String>());
-    let (rest, a) = register(rest)?;
-    trace!("parsed register: <{:?}...>", rest.chars().take(20).collect::<String>());
-    let (rest, _) = next_token(rest)?;
-    trace!("skipped ahead: <{:?}...>", rest.chars().take(20).collect::<String>());
-    let (rest, b) = register(rest)?;
-    trace!("parsed register: <{:?}...>", rest.chars().take(20).collect::<String>());
-    let (rest, next) = opt(peek(tag(",")))(rest)?;
-    trace!("peeked ahead: <{:?}...>", rest.chars().take(20).collect::<String>());
-    match next {
-        Some(_) => there_must_be_a_next_value(rest, opcode, a, b),
-        None => there_could_be_a_next_value(rest, opcode, a, b),
+fn origin_directive(input: &str) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (addr_at, addr)) = spanned(cut(context("Origin (Expected an address from 0x0000 to 0x07FE)", address)))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found origin: {}", addr);
+        let mut temp = Instruction::new(Opcode::_Org);
+        temp.add_address(addr);
+        temp.operand_offsets.push(addr_at);
+        temp
+    }))
+}
+
+/// `.align bytes` where bytes is a power of two, or `.fill count, instruction`
+fn count_directive<'a>(input: &'a str, opcode: Opcode, instructions: &InstructionSet) -> Res<&'a str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (count_at, count)) = match opcode {
+        Opcode::_Align => spanned(cut(context(
+            "Alignment (Expected a power of two from 2 to 2048)",
+            verify(number::<u16>, |n| n.is_power_of_two() && (2..=0x800).contains(n))
+        )))(rest)?,
+        _ => spanned(cut(context(
+            "Fill (Expected a count from 1 to 1024)",
+            verify(number::<u16>, |n| (1..=0x400).contains(n))
+        )))(rest)?
+    };
+    let mut temp = Instruction::new(opcode);
+    temp.add_operand(Operand::Count(count));
+    temp.operand_offsets.push(count_at);
+
+    if opcode == Opcode::_Align {
+        let (rest, _) = next_instruction(rest)?;
+        trace!("Found alignment: {}", count);
+        return Ok((rest, temp));
     }
+
+    let (rest, _) = next_token(rest)?;
+    let (rest, (instruction_at, instruction)) = spanned(cut(|i| parse_instruction(i, instructions)))(rest)?;
+    trace!("Found fill: {} {}", count, instruction);
+    temp.add_operand(Operand::Instr(Box::new(instruction)));
+    temp.operand_offsets.push(instruction_at);
+    Ok((rest, temp))
 }
 
+/// `.filler instruction` sets what `.org` and `.align` fill their gaps with. Every gap is filled one
+/// instruction at a time, so pseudo-instructions that expand to several (like `CMPI`) cannot be used
+fn filler_directive<'a>(input: &'a str, instructions: &InstructionSet) -> Res<&'a str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (instruction_at, instruction)) = spanned(cut(context(
+        "Filler (Expected a single instruction)",
+        verify(|i| parse_instruction(i, instructions), |i: &Instruction| !i.opcode.is_directive())
+    )))(rest)?;
+    Ok((rest, {
+        trace!("Found filler: {}", instruction);
+        let mut temp = Instruction::new(Opcode::_Filler);
+        temp.add_operand(Operand::Instr(Box::new(instruction)));
+        temp.operand_offsets.push(instruction_at);
+        temp
+    }))
+}
 
-/// Holy shit, there was a value there!!!
-fn there_is_a_next_value(input: &str, opcode: Opcode, a: Register, b: Register, o: Operand) -> Res<&str, Instruction> {
-    match o {
-        Operand::Offset(i) => {
-            Ok((input, {
-                trace!("Found instruction: {} {}, {}, {}", opcode, a, b, i);
-                let mut temp = Instruction::new(opcode);
-                temp.add_register(a);
-                temp.add_register(b);
-                temp.add_offset(i);
-                temp
-            }))
-        }
-        Operand::Def(d) => {
-            Ok((input, {
-                trace!("Found instruction: {} {}, {}, {}", opcode, a, b, d);
-                let mut temp = Instruction::new(opcode);
-                temp.add_register(a);
-                temp.add_register(b);
-                temp.add_definition(d);
-                temp
-            }))
+/// `.stack sp, length` reserves `length` bytes of data memory for `PUSH` and `POP`. Their name has
+/// an `@` so it cannot clash with a name in the source
+fn stack_directive(input: &str) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (sp_at, sp)) = spanned(operand_register)(rest)?;
+    let (rest, _) = next_token(rest)?;
+    let (rest, (length_at, length)) = spanned(cut(context(
+        "Stack (Expected a length from 1 to 240)",
+        verify(number::<u8>, |n| (1..=Port::PixelX as u8).contains(n))
+    )))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found stack: {} bytes, {}", length, sp);
+        let mut temp = Instruction::new(Opcode::_Stack);
+        temp.add_definition(Definition::new_opr("@stack").unwrap());
+        temp.add_immediate(Immediate::new(length as i16).unwrap());
+        temp.add_operand(sp);
+        temp.operand_offsets = vec![sp_at, length_at, sp_at];
+        temp
+    }))
+}
+
+/// `.sprite "file"` draws the image in the bottom left corner, `.sprite "file", x, y` with its
+/// bottom left corner somewhere else. Each coordinate is a number or a register that holds it
+/// when the sprite is drawn
+fn sprite_directive(input: &str) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (path_at, path)) = spanned(cut(string))(rest)?;
+    let comma = |input| pair(space0, tag(","))(input);
+    let (rest, position) = opt(pair(
+        preceded(comma, cut(preceded(space0, spanned(sprite_coordinate)))),
+        preceded(cut(comma), cut(preceded(space0, spanned(sprite_coordinate))))
+    ))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found sprite: {} at {:?}", path, position);
+        let mut temp = Instruction::new(Opcode::_Sprite);
+        temp.add_text(path.to_string());
+        temp.operand_offsets = vec![path_at];
+        if let Some(((x_at, x), (y_at, y))) = position {
+            temp.add_operand(x);
+            temp.add_operand(y);
+            temp.operand_offsets.extend([x_at, y_at]);
         }
-        _ => {
-            // Wrong value >:(
-            error!("Error: Unexpected operand for {opcode}: {o:?}");
-            context("Instruction (Unexpected Operand)", fail)(input)
-        },
-    }
+        temp
+    }))
 }
 
-/// There was no value there after all :(
-fn there_is_not_a_next_value(input: &str, opcode: Opcode, a: Register, b: Register) -> Res<&str, Instruction> {
-    Ok((input, {
-        trace!("Found instruction: {} {}, {}", opcode, a, b);
+fn sprite_coordinate(input: &str) -> Res<&str, Operand> {
+    use Operand as O;
+    context("Sprite (Expected a register or a coordinate from 0 to 31)", alt((
+        map(register, O::Reg),
+        map(verify(number::<u8>, |n| (*n as usize) < SCREEN_SIZE), |n| O::Imm(Immediate::new(n as i16).unwrap())),
+        map(alias_usage, O::Alias)
+    )))(input)
+}
+
+/// `.if a < b` and `.while a < b` compare a register to a register or a constant. With only a
+/// condition, e.g. `.if zero`, they test the flags that the instruction before them left
+fn condition_directive(input: &str, opcode: Opcode) -> Res<&str, Instruction> {
+    use Operand as O;
+    let (rest, _) = cut(space1)(input)?;
+    let left = alt((map(register, O::Reg), map(alias_usage, O::Alias)));
+    if let Ok((rest, (a_at, a))) = spanned(left)(rest) {
+        let (rest, _) = space0(rest)?;
+        let (rest, (comparison_at, comparison)) = spanned(cut(comparison))(rest)?;
+        let (rest, _) = space0(rest)?;
+        let (rest, (b_at, b)) = spanned(alt((map(register, O::Reg), map(virtual_register, O::Alias), operand_immediate)))(rest)?;
+        let (rest, _) = next_instruction(rest)?;
+        return Ok((rest, {
+            trace!("Found instruction: {} {} {} {}", opcode, a, comparison, b);
+            let mut temp = Instruction::new(opcode);
+            temp.add_operand(a);
+            temp.add_operand(O::Compare(comparison));
+            temp.add_operand(b);
+            temp.operand_offsets = vec![a_at, comparison_at, b_at];
+            temp
+        }));
+    }
+
+    let (rest, (cond_at, cond)) = spanned(cut(context(
+        "Condition (Expected a comparison like r1 < r2, or a condition)",
+        condition
+    )))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found instruction: {} {}", opcode, cond);
         let mut temp = Instruction::new(opcode);
-        temp.add_register(a);
-        temp.add_register(b);
+        temp.add_condition(cond);
+        temp.operand_offsets = vec![cond_at];
         temp
     }))
 }
 
-pub fn parse_instruction(input: &str) -> Res<&str, Instruction> {
-    trace!("parse_instruction current input: <{:?}...>", input.chars().take(20).collect::<String>());
-    let (rest, opcode) = leading_ws(opcode)(input)?;
-    trace!("{} parsed, remaining: <{:?}...>", opcode, rest.chars().take(20).collect::<String>());
-    match opcode {
-        Opcode::NOP | Opcode::HLT | Opcode::RET => no_operands(rest, opcode),
-        Opcode::ADD | Opcode::SUB | Opcode::NOR | Opcode::AND | Opcode::XOR => three_operands(rest, opcode),
-        Opcode::RSH | Opcode::CMP | Opcode::MOV | Opcode::LSH | Opcode::NOT | Opcode::NEG => two_operands(rest, opcode),
-        Opcode::INC | Opcode::DEC => one_operand(rest, opcode),
-        Opcode::LDI | Opcode::ADI => immediate_instructions(rest, opcode),
-        Opcode::JMP | Opcode::CAL => address_instructions(rest, opcode),
-        Opcode::BRH => branch_instruction(rest, opcode),
-        Opcode::LOD | Opcode::STR => load_and_store(rest, opcode),
-        _ => {
-            error!("Error: Invalid opcode (How the fuck?)");
-            cut(context("Instruction (Invalid Opcode)", fail))(rest)
-        }
+/// `.loop counter` repeats until `.endloop` counts the register down to zero. Without a register
+/// it is not a loop but a label called `.loop`
+fn loop_directive(input: &str) -> Res<&str, Instruction> {
+    use Operand as O;
+    let (rest, (counter_at, counter)) = preceded(
+        space1,
+        spanned(alt((map(register, O::Reg), map(alias_usage, O::Alias))))
+    )(input)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, {
+        trace!("Found loop: {}", counter);
+        let mut temp = Instruction::new(Opcode::_Loop);
+        temp.add_operand(counter);
+        temp.operand_offsets = vec![counter_at];
+        temp
+    }))
+}
+
+/// `.pair name high, low` names two registers for the 16-bit operations
+fn pair_directive(input: &str) -> Res<&str, Instruction> {
+    let (rest, _) = cut(space1)(input)?;
+    let (rest, (name_at, name)) = spanned(cut(context("Pair (Invalid name)", identifier)))(rest)?;
+    let (rest, _) = next_token(rest)?;
+    let (rest, high) = cut(register)(rest)?;
+    let (rest, _) = next_token(rest)?;
+    let (rest, low) = cut(register)(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    match RegisterPair::new_def(name, high, low) {
+        Some(pair) => Ok((rest, {
+            trace!("Found pair: {}", pair);
+            let mut temp = Instruction::new(Opcode::_Pair);
+            temp.add_operand(Operand::Pair(pair));
+            temp.operand_offsets.push(name_at);
+            temp
+        })),
+        None => cut(context("Pair (Invalid name)", fail))(input)
     }
 }
 
-pub fn parse_labels(input: &str) -> Res<&str, Instruction> {
-    trace!("attempting to parse label: <{:?}>", input.chars().take(20).collect::<String>());
-    let (rest, label) = label_define(input)?;
+pub fn parse_aliases(input: &str) -> Res<&str, Instruction> {
+    trace!("attempting to parse alias: <{:?}>", input.chars().take(40).collect::<String>());
+    let (rest, alias) = alias(input)?;
     Ok((rest, {
-        trace!("Found label: {}", label);
-        let mut temp = Instruction::new(Opcode::_Label);
-        temp.add_label_name(label.to_string());
+        trace!("Found alias: {}", alias);
+        let mut temp = Instruction::new(Opcode::_Alias);
+        temp.add_alias(alias);
         temp
     }))
 }
 
+/// The name, parameters, opcode and operands of an instruction alias, with where the opcode and
+/// each operand start
+pub type AliasDefinition<'a> = (&'a str, Vec<&'a str>, (usize, Opcode), Vec<(usize, Operand)>);
+
+/// `name a, b = OPCODE a, r0, b`, an instruction alias from a project's config, see
+/// [`InstructionSet`]. Parameters in the operands are names, the instruction set checks them
+pub fn parse_instruction_alias(input: &str) -> Res<&str, AliasDefinition<'_>> {
+    use Operand as O;
+    let (rest, name) = context("Instruction alias (Expected a name)", identifier)(input)?;
+    let (rest, parameters) = opt(preceded(space1, separated_list1(pair(tag(","), space0), identifier)))(rest)?;
+    let (rest, _) = cut(context("Instruction alias (Expected =)", tuple((space0, tag("="), space0))))(rest)?;
+    let (rest, target) = spanned(opcode)(rest)?;
+    let (rest, operands) = opt(preceded(space1, separated_list1(
+        tuple((space0, tag(","), space0)),
+        spanned(cut(context(
+            "Instruction alias (Expected a parameter, register or number)",
+            alt((
+                map(register, O::Reg),
+                map(immediate, O::Imm),
+                map(identifier, |name| O::Name(name.to_string()))
+            ))
+        )))
+    )))(rest)?;
+    let (rest, _) = next_instruction(rest)?;
+    Ok((rest, (name, parameters.unwrap_or_default(), target, operands.unwrap_or_default())))
+}
+
 pub fn parse_definitions(input: &str) -> Res<&str, Instruction> {
     trace!("attempting to parse definitions: <{:?}>", input.chars().take(40).collect::<String>());
     let (rest, define) = opt(define)(input)?;
@@ -312,6 +720,19 @@ pub fn parse_definitions(input: &str) -> Res<&str, Instruction> {
     }
 }
 
+pub fn operand_register(input: &str) -> Res<&str, Operand> {
+    use Operand as O;
+    trace!("operand (reg) current input: <{:?}>", input.chars().take(20).collect::<String>());
+    let (rest, operand) = cut(context(
+        "Register (Expected register or alias here)",
+        alt((
+            map(register, O::Reg),
+            map(alias_usage, O::Alias)
+        ))
+    ))(input)?;
+    Ok((rest, operand))
+}
+
 pub fn operand_offset(input: &str) -> Res<&str, Operand> {
     use Operand as O;
     type Verbose = VerboseError<&'static str>;
@@ -336,11 +757,21 @@ pub fn operand_immediate(input: &str) -> Res<&str, Operand> {
     Ok((rest, operand))
 }
 
+pub fn operand_condition(input: &str) -> Res<&str, Operand> {
+    trace!("operand (cond) current input: <{:?}>", input.chars().take(20).collect::<String>());
+    let (rest, operand) = map(condition, Operand::Cond)(input)?;
+    Ok((rest, operand))
+}
+
 pub fn operand_address(input: &str) -> Res<&str, Operand> {
     use Operand as O;
     type Verbose = VerboseError<&'static str>;
-    trace!("operand (imm) current input: <{:?}>", input.chars().take(20).collect::<String>());
+    trace!("operand (addr) current input: <{:?}>", input.chars().take(20).collect::<String>());
     let (rest, operand) = cut(alt((
+        map(
+            verify(expression, |n| !matches!(n, Node::Label(_) | Node::Number(_))),
+            |n| O::Expr(Expression::new(n))
+        ),
         map_res(label_usage, |o| Ok::<O, Verbose>(O::Label(o))),
         map_res(address, |a| Ok::<O, Verbose>(O::Addr(a)))
     )))(input)?;
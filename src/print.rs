@@ -2,13 +2,16 @@ use crate::architecture::batpu2::instruction::Instruction;
 use crate::architecture::batpu2::opcode::Opcode;
 use crate::architecture::batpu2::operand::Operand;
 
+/// Width of the instructions column, longer instructions push the source column to the right
+const INSTRUCTION_WIDTH: usize = 40;
+
 pub struct AssemblyPrinter<'a> {
     program: &'a [Instruction],
     output: Option<String>
 }
 
 impl<'a> AssemblyPrinter<'a> {
-    pub fn new(program: &[Instruction]) -> AssemblyPrinter {
+    pub fn new(program: &[Instruction]) -> AssemblyPrinter<'_> {
         AssemblyPrinter {
             program,
             output: None
@@ -17,10 +20,10 @@ impl<'a> AssemblyPrinter<'a> {
 
     pub fn print(&mut self) -> String {
         self.output = Some("".to_string());
-        self.emit("\naddr |     encoding     |       instructions\n");
-        self.emit("---- | ---------------- | -----------------------\n");
+        self.emit(&format!("\naddr |     encoding     | {:<INSTRUCTION_WIDTH$} | source\n", "      instructions"));
+        self.emit(&format!("---- | ---------------- | {} | ------\n", "-".repeat(INSTRUCTION_WIDTH)));
         for instruction in self.program {
-            self.print_instruction(&instruction);
+            self.print_instruction(instruction);
         }
         let s = self.output.clone().unwrap();
         self.output = None;
@@ -44,17 +47,33 @@ impl<'a> AssemblyPrinter<'a> {
         } else {
             self.emit(" ---------------- |");
         }
-        self.print_opcode(opcode);
-        self.print_operands(operands);
-        self.emit("\n");
+        let start = self.output.as_ref().unwrap().len();
+        match &instruction.mnemonic {
+            // The condition is part of the mnemonic
+            Some(mnemonic) => {
+                self.emit(&format!("    {mnemonic}  "));
+                let operands: Vec<Operand> = operands.iter().filter(|o| !matches!(o, Operand::Cond(_))).cloned().collect();
+                self.print_operands(&operands);
+            },
+            None => {
+                self.print_opcode(opcode);
+                self.print_operands(operands);
+            }
+        }
+        let width = self.output.as_ref().unwrap()[start..].chars().count();
+        let padding = INSTRUCTION_WIDTH.saturating_sub(width) + 2;
+        self.emit(&format!("{}| {}\n", " ".repeat(padding), instruction.source));
     }
 
-    fn print_operands(&mut self, operands: &Vec<Operand>) {
-        for i in 0..operands.len() {
-            if i > 0 {
+    fn print_operands(&mut self, operands: &[Operand]) {
+        for (i, operand) in operands.iter().enumerate() {
+            // Comparisons read as `a < b`, without commas
+            if i > 0 && !matches!((operand, &operands[i - 1]), (Operand::Compare(_), _) | (_, Operand::Compare(_))) {
                 self.emit(", ");
+            } else if i > 0 {
+                self.emit(" ");
             }
-            self.emit(&format!("{}", operands[i]));
+            self.emit(&format!("{}", operand));
         }
     }
 
@@ -63,8 +82,8 @@ impl<'a> AssemblyPrinter<'a> {
             self.emit(".");
             return;
         }
-        if *opcode == Opcode::_Definition {
-            self.emit("define ");
+        if opcode.is_directive() {
+            self.emit(&format!("{opcode} "));
             return;
         }
         self.emit(&format!("    {opcode}  "));
@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::{Access, Opcode};
+use crate::architecture::batpu2::operand::definition::Definition;
+use crate::architecture::batpu2::operand::Operand;
+use crate::architecture::batpu2::operand::register::Register;
+use crate::instructions::operand_fields;
+use crate::resolve::{find_anonymous, is_anonymous, is_generated, is_global_label};
+use crate::source::SourceLocation;
+
+#[derive(Debug, Error)]
+pub enum RegisterError {
+    #[error("{location}: No register is left for {name}, and spilling it to data memory needs two registers that the program does not use")]
+    OutOfRegisters {
+        location: SourceLocation,
+        name: String
+    }
+}
+
+/// Virtual registers belong to a routine, which is everything under one global label
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Virtual {
+    file: String,
+    routine: String,
+    name: String
+}
+
+/// Where the value of a virtual register is kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Home {
+    Register(Register),
+    Memory
+}
+
+/// Gives every virtual register (`%count`) a register that is free wherever the virtual register
+/// is live. Registers that the program names anywhere are never handed out, and virtual registers
+/// that are live across a `CAL` get one that no other routine uses. When there are not enough,
+/// the rest are spilled to a `.var` each and two registers are set aside to load them into
+pub fn allocate_registers(program: Vec<Instruction>) -> Result<Vec<Instruction>, RegisterError> {
+    let routines = routines(&program);
+    let mut virtuals: Vec<(Virtual, SourceLocation)> = Vec::new();
+    let mut ids: HashMap<Virtual, usize> = HashMap::new();
+    let mut occurrences: Vec<Vec<(usize, usize)>> = Vec::with_capacity(program.len());
+    for (i, instruction) in program.iter().enumerate() {
+        let mut found = Vec::new();
+        for (n, name) in virtual_operands(instruction) {
+            let key = Virtual {
+                file: instruction.source.file.clone(),
+                routine: routines[i].clone(),
+                name
+            };
+            let id = *ids.entry(key.clone()).or_insert_with(|| {
+                virtuals.push((key, instruction.operand_source(n).clone()));
+                virtuals.len() - 1
+            });
+            found.push((n, id));
+        }
+        occurrences.push(found);
+    }
+
+    if virtuals.is_empty() {
+        return Ok(program);
+    }
+    info!("Allocating registers...");
+
+    // What each real instruction reads and writes, directives do neither
+    let mut reads: Vec<HashSet<usize>> = vec![HashSet::new(); program.len()];
+    let mut writes: Vec<HashSet<usize>> = vec![HashSet::new(); program.len()];
+    for (i, instruction) in program.iter().enumerate() {
+        let (read, written) = accesses(instruction.opcode);
+        for (n, id) in &occurrences[i] {
+            if read.contains(n) {
+                reads[i].insert(*id);
+            }
+            if written.contains(n) {
+                writes[i].insert(*id);
+            }
+        }
+    }
+
+    let successors = control_flow(&program, &routines);
+    let (live_in, live_out) = liveness(&successors, &reads, &writes);
+    for (i, instruction) in program.iter().enumerate() {
+        let entry = i == 0 || routines[i] != routines[i - 1] || instruction.source.file != program[i - 1].source.file;
+        if entry {
+            for id in &live_in[i] {
+                let (virtual_register, location) = &virtuals[*id];
+                if virtual_register.routine == routines[i] && virtual_register.file == instruction.source.file {
+                    warn!("{}: {} is read before anything is written to it", location, virtual_register.name);
+                }
+            }
+        }
+    }
+
+    // Virtual registers that are live at the same time cannot share a register
+    let mut interference: Vec<HashSet<usize>> = vec![HashSet::new(); virtuals.len()];
+    let mut interfere = |live: &HashSet<usize>| {
+        for a in live {
+            for b in live {
+                if a != b {
+                    interference[*a].insert(*b);
+                }
+            }
+        }
+    };
+    for i in 0..program.len() {
+        interfere(&live_in[i]);
+        interfere(&live_out[i].union(&writes[i]).copied().collect());
+    }
+    // Any other routine could be the one that is called
+    for (i, instruction) in program.iter().enumerate().filter(|(_, i)| i.opcode == Opcode::CAL) {
+        for id in &live_out[i] {
+            let (caller, _) = &virtuals[*id];
+            trace!("{}: {} is live across the call", instruction.source, caller.name);
+            for (other, (callee, _)) in virtuals.iter().enumerate() {
+                if callee.routine != caller.routine || callee.file != caller.file {
+                    interference[*id].insert(other);
+                    interference[other].insert(*id);
+                }
+            }
+        }
+    }
+
+    let named = named_registers(&program);
+    let mut free: Vec<Register> = Register::ALL[1..].iter().filter(|r| !named.contains(r)).copied().collect();
+    let mut homes = color(&interference, &free);
+    let mut spill_registers: Option<(Register, Register)> = None;
+    if let Some(spilled) = homes.iter().position(|h| *h == Home::Memory) {
+        if free.len() < 2 {
+            let (virtual_register, location) = &virtuals[spilled];
+            return Err(RegisterError::OutOfRegisters {
+                location: location.clone(),
+                name: virtual_register.name.clone()
+            });
+        }
+        let second = free.pop().unwrap();
+        let first = free.pop().unwrap();
+        spill_registers = Some((first, second));
+        homes = color(&interference, &free);
+    }
+
+    for ((virtual_register, location), home) in virtuals.iter().zip(&homes) {
+        match home {
+            Home::Register(register) => trace!("{}: {} is in {}", location, virtual_register.name, register),
+            Home::Memory => debug!("{}: {} is spilled to data memory", location, virtual_register.name)
+        }
+    }
+    debug!("{} virtual registers allocated, {} of them spilled",
+        virtuals.len(), homes.iter().filter(|h| **h == Home::Memory).count());
+
+    let slot = |id: usize| {
+        let (virtual_register, _) = &virtuals[id];
+        Operand::Def(Definition::new_opr(&format!("{}@{}", virtual_register.name, virtual_register.routine)).unwrap())
+    };
+    let mut declared: HashSet<usize> = HashSet::new();
+    let mut allocated: Vec<Instruction> = Vec::with_capacity(program.len());
+    for (i, mut instruction) in program.into_iter().enumerate() {
+        for (_, id) in &occurrences[i] {
+            if homes[*id] == Home::Memory && declared.insert(*id) {
+                let mut variable = Instruction::new(Opcode::_Var);
+                variable.add_operand(slot(*id));
+                variable.source = instruction.source.clone();
+                allocated.push(variable);
+            }
+        }
+
+        let spilled: Vec<usize> = occurrences[i].iter().map(|(_, id)| *id).filter(|id| homes[*id] == Home::Memory).collect();
+        let (first, second) = match spill_registers {
+            Some(registers) if !spilled.is_empty() && !instruction.opcode.is_directive() => registers,
+            _ => {
+                bind(&mut instruction, &occurrences[i], |id| match homes[id] {
+                    Home::Register(register) => Some(register),
+                    Home::Memory => None
+                });
+                allocated.push(instruction);
+                continue;
+            }
+        };
+
+        // Spilled values are loaded before the instruction and the one it writes is stored after
+        let mut temporaries: HashMap<usize, Register> = HashMap::new();
+        let mut generated = Vec::new();
+        for id in spilled.iter().filter(|id| reads[i].contains(id)) {
+            if temporaries.contains_key(id) {
+                continue;
+            }
+            let temporary = if temporaries.is_empty() { first } else { second };
+            temporaries.insert(*id, temporary);
+            generated.push(load(temporary, slot(*id)));
+            generated.push(memory(Opcode::LOD, temporary, temporary));
+        }
+        let written = spilled.iter().find(|id| writes[i].contains(id)).copied();
+        if let Some(id) = written {
+            temporaries.entry(id).or_insert(first);
+        }
+        bind(&mut instruction, &occurrences[i], |id| match homes[id] {
+            Home::Register(register) => Some(register),
+            Home::Memory => temporaries.get(&id).copied()
+        });
+
+        let source = instruction.source.clone();
+        generated.push(instruction);
+        if let Some(id) = written {
+            let value = temporaries[&id];
+            let address = if value == first { second } else { first };
+            generated.push(load(address, slot(id)));
+            generated.push(memory(Opcode::STR, address, value));
+        }
+        allocated.extend(generated.into_iter().map(|mut generated| {
+            generated.source = source.clone();
+            generated
+        }));
+    }
+    Ok(allocated)
+}
+
+/// The routine of every instruction, named after its global label
+fn routines(program: &[Instruction]) -> Vec<String> {
+    let mut routine = String::new();
+    let mut file = "";
+    program
+        .iter()
+        .map(|instruction| {
+            if instruction.source.file != file {
+                file = &instruction.source.file;
+                routine.clear();
+            }
+            if let (Opcode::_Label, Some(Operand::Name(name))) = (instruction.opcode, instruction.operands.first()) {
+                if is_global_label(name) {
+                    routine = name.clone();
+                }
+            }
+            routine.clone()
+        })
+        .collect()
+}
+
+/// The virtual registers that an instruction names, with their operand index
+fn virtual_operands(instruction: &Instruction) -> Vec<(usize, String)> {
+    instruction.operands
+        .iter()
+        .enumerate()
+        .filter_map(|(n, operand)| match operand {
+            Operand::Alias(alias) if alias.is_virtual() => Some((n, alias.name.clone())),
+            _ => None
+        })
+        .collect()
+}
+
+/// Which operands of an instruction are read and which are written
+fn accesses(opcode: Opcode) -> (Vec<usize>, Vec<usize>) {
+    let fields = operand_fields(opcode).unwrap_or_default();
+    let operands = |access: fn(Access) -> bool| fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| field.access().is_some_and(access))
+        .map(|(i, _)| i)
+        .collect();
+    (operands(Access::reads), operands(Access::writes))
+}
+
+/// Every instruction that can run after each one. A call returns to the instruction after it, and
+/// jumps to another file leave the program as far as virtual registers are concerned. A jump to an
+/// address or an expression could go to any label in the file
+fn control_flow(program: &[Instruction], routines: &[String]) -> Vec<Vec<usize>> {
+    let qualified = |name: &str, routine: &str| match name.strip_prefix('.') {
+        Some(local) if !is_generated(local) => format!("{routine}.{local}"),
+        _ => name.to_string()
+    };
+
+    let mut labels: HashMap<(String, String), usize> = HashMap::new();
+    let mut anonymous: Vec<(usize, Instruction)> = Vec::new();
+    for (i, instruction) in program.iter().enumerate().filter(|(_, i)| i.opcode == Opcode::_Label) {
+        let name = instruction.operands[0].to_string();
+        if is_anonymous(&name) {
+            anonymous.push((i, instruction.clone()));
+        } else {
+            labels.insert((instruction.source.file.clone(), qualified(&name, &routines[i])), i);
+        }
+    }
+
+    let targets = |i: usize, operand: &Operand| -> Vec<usize> {
+        let file = &program[i].source.file;
+        match operand {
+            Operand::Label(label) if is_anonymous(&label.name) => {
+                find_anonymous(&anonymous, i, file, &label.name).map(|(target, _)| *target).into_iter().collect()
+            },
+            Operand::Label(label) => {
+                labels.get(&(file.clone(), qualified(&label.name, &routines[i]))).copied().into_iter().collect()
+            },
+            _ => labels.iter().filter(|((f, _), _)| f == file).map(|(_, target)| *target).collect()
+        }
+    };
+
+    (0..program.len())
+        .map(|i| {
+            let next: Vec<usize> = if i + 1 < program.len() { vec![i + 1] } else { Vec::new() };
+            let operands = &program[i].operands;
+            match program[i].opcode {
+                Opcode::JMP => targets(i, &operands[0]),
+                Opcode::BRH => [targets(i, &operands[1]), next].concat(),
+                Opcode::RET | Opcode::HLT => Vec::new(),
+                _ => next
+            }
+        })
+        .collect()
+}
+
+/// The virtual registers that are live before and after each instruction
+fn liveness(successors: &[Vec<usize>], reads: &[HashSet<usize>], writes: &[HashSet<usize>]) -> (Vec<HashSet<usize>>, Vec<HashSet<usize>>) {
+    let mut live_in: Vec<HashSet<usize>> = vec![HashSet::new(); successors.len()];
+    let mut live_out: Vec<HashSet<usize>> = vec![HashSet::new(); successors.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..successors.len()).rev() {
+            let out: HashSet<usize> = successors[i].iter().flat_map(|s| live_in[*s].iter().copied()).collect();
+            let mut live: HashSet<usize> = out.difference(&writes[i]).copied().collect();
+            live.extend(&reads[i]);
+            if live != live_in[i] || out != live_out[i] {
+                live_in[i] = live;
+                live_out[i] = out;
+                changed = true;
+            }
+        }
+    }
+    (live_in, live_out)
+}
+
+/// Registers the program names itself, which are left alone
+fn named_registers(program: &[Instruction]) -> HashSet<Register> {
+    let mut named = HashSet::from([Register::R0]);
+    for instruction in program {
+        for operand in &instruction.operands {
+            match operand {
+                Operand::Pair(pair) => named.extend(pair.registers.iter().flat_map(|(high, low)| [*high, *low])),
+                Operand::Instr(inner) => named.extend(named_registers(std::slice::from_ref(inner))),
+                _ => named.extend(operand.register())
+            }
+        }
+    }
+    named
+}
+
+/// Hands out registers in order of first use, each gets the lowest register that none of the
+/// virtual registers it interferes with has
+fn color(interference: &[HashSet<usize>], free: &[Register]) -> Vec<Home> {
+    let mut homes: Vec<Home> = Vec::with_capacity(interference.len());
+    for neighbours in interference {
+        let taken: HashSet<Register> = neighbours
+            .iter()
+            .filter_map(|n| match homes.get(*n) {
+                Some(Home::Register(register)) => Some(*register),
+                _ => None
+            })
+            .collect();
+        homes.push(match free.iter().find(|r| !taken.contains(r)) {
+            Some(register) => Home::Register(*register),
+            None => Home::Memory
+        });
+    }
+    homes
+}
+
+fn bind(instruction: &mut Instruction, occurrences: &[(usize, usize)], home: impl Fn(usize) -> Option<Register>) {
+    for (n, id) in occurrences {
+        if let Operand::Alias(alias) = &mut instruction.operands[*n] {
+            alias.register = home(*id);
+        }
+    }
+}
+
+fn load(register: Register, slot: Operand) -> Instruction {
+    let mut temp = Instruction::new(Opcode::LDI);
+    temp.add_register(register);
+    temp.add_operand(slot);
+    temp
+}
+
+fn memory(opcode: Opcode, base: Register, register: Register) -> Instruction {
+    let mut temp = Instruction::new(opcode);
+    temp.add_register(base);
+    temp.add_register(register);
+    temp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ids: &[usize]) -> HashSet<usize> {
+        ids.iter().copied().collect()
+    }
+
+    #[test]
+    fn operand_accesses() {
+        assert_eq!(accesses(Opcode::ADD), (vec![0, 1], vec![2]));
+        assert_eq!(accesses(Opcode::LOD), (vec![0], vec![1]));
+        // Pseudo-instructions go by what their built-in alias stands for
+        assert_eq!(accesses(Opcode::LSH), (vec![0], vec![1]));
+        assert_eq!(accesses(Opcode::INC), (vec![0], vec![0]));
+        assert_eq!(accesses(Opcode::CMP), (vec![0, 1], vec![]));
+    }
+
+    #[test]
+    fn straight_line_liveness() {
+        // %a = ..., %b = ..., ... = %a + %b
+        let successors = vec![vec![1], vec![2], vec![]];
+        let reads = vec![set(&[]), set(&[]), set(&[0, 1])];
+        let writes = vec![set(&[0]), set(&[1]), set(&[])];
+        let (live_in, live_out) = liveness(&successors, &reads, &writes);
+        assert_eq!(live_in, [set(&[]), set(&[0]), set(&[0, 1])]);
+        assert_eq!(live_out, [set(&[0]), set(&[0, 1]), set(&[])]);
+    }
+
+    #[test]
+    fn loop_liveness() {
+        // %a = ..., then a loop that reads %a and writes %b, which is read after it
+        let successors = vec![vec![1], vec![2], vec![1, 3], vec![]];
+        let reads = vec![set(&[]), set(&[0]), set(&[]), set(&[1])];
+        let writes = vec![set(&[0]), set(&[1]), set(&[]), set(&[])];
+        let (live_in, live_out) = liveness(&successors, &reads, &writes);
+        assert_eq!(live_out[2], set(&[0, 1]), "%a is still needed when the loop goes around");
+        assert_eq!(live_in[1], set(&[0]));
+    }
+
+    #[test]
+    fn coloring() {
+        // %a and %b are live at the same time, %c is not live with either of them
+        let interference = vec![set(&[1]), set(&[0]), set(&[])];
+        let homes = color(&interference, &[Register::R1, Register::R2]);
+        assert_eq!(homes, [Home::Register(Register::R1), Home::Register(Register::R2), Home::Register(Register::R1)]);
+
+        // Three virtual registers live at once, with only two registers to go around
+        let interference = vec![set(&[1, 2]), set(&[0, 2]), set(&[0, 1])];
+        let homes = color(&interference, &[Register::R1, Register::R2]);
+        assert_eq!(homes, [Home::Register(Register::R1), Home::Register(Register::R2), Home::Memory]);
+    }
+}
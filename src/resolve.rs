@@ -1,57 +1,298 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::Opcode;
 use crate::architecture::batpu2::opcode::Opcode::_Label;
 use crate::architecture::batpu2::operand::Operand;
+use crate::source::SourceLocation;
+use crate::suggest::Suggestion;
+
+/// Symbols are looked up by the file they are in and their name
+type Symbols = HashMap<(String, String), Instruction>;
+
+/// Names listed by `.global` or `.extern`, keyed like `Symbols`, along with where they are listed
+pub(crate) type Declarations = HashMap<(String, String), SourceLocation>;
 
 #[derive(Debug, Error)]
 pub enum ResolveError {
-    #[error("Invalid label: {0}")]
-    InvalidLabel(String),
-    #[error("Unknown label: {0}")]
-    UnknownLabel(String),
-    #[error("Missing address for label: {0}")]
-    MissingAddress(String),
+    #[error("{location}: Invalid label: {name}")]
+    InvalidLabel {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Unknown label: {name}{suggestion}")]
+    UnknownLabel {
+        location: SourceLocation,
+        name: String,
+        suggestion: Suggestion
+    },
+    #[error("{location}: Missing address for label: {name}")]
+    MissingAddress {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Local label .{name} is not inside a global label")]
+    OrphanLocalLabel {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{second}: {name} is exported with .global, but {first} already exports it")]
+    ConflictingGlobal {
+        name: String,
+        first: SourceLocation,
+        second: SourceLocation
+    },
+    #[error("{location}: {name} is exported with .global, but is not defined in this file")]
+    UndefinedGlobal {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: {name} is imported with .extern, but no file exports it")]
+    UndefinedExtern {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: {name} is imported with .extern, but this file also defines it")]
+    ConflictingExtern {
+        location: SourceLocation,
+        name: String
+    },
+    #[error("{location}: Unknown label .{name}, it is exported at {exporter} (add .extern {name})")]
+    NotImported {
+        location: SourceLocation,
+        name: String,
+        exporter: SourceLocation
+    },
 }
 
 // This has to be done inline because the compiler is a bitch
 pub fn resolve_program(program: &mut [Instruction]) -> Result<(), ResolveError> {
-    let mut labels: HashMap<String, Instruction> = HashMap::new();
+    let mut labels: Symbols = HashMap::new();
+    let mut anonymous: Vec<(usize, Instruction)> = Vec::new();
     info!("Resolving program...");
-    for i in 0..program.len() {
-        if program[i].opcode == _Label {
-            let label: String = program[i].operands[0].to_string();
-            if labels.contains_key(&label) {
-                return Err(ResolveError::InvalidLabel(label));
+    scope_local_labels(program)?;
+
+    for (i, instruction) in program.iter().enumerate() {
+        if instruction.opcode == _Label {
+            let label: String = instruction.operands[0].to_string();
+            if is_anonymous(&label) {
+                anonymous.push((i, instruction.clone()));
+                continue;
             }
 
-            trace!("Found label at address {}: .{}", program[i].clone().location.unwrap(), label);
+            let key = (instruction.source.file.clone(), label);
+            if labels.contains_key(&key) {
+                return Err(ResolveError::InvalidLabel {
+                    location: instruction.source.clone(),
+                    name: key.1
+                });
+            }
 
-            if program[i].location.is_none() {
-                return Err(ResolveError::MissingAddress(label));
+            if instruction.location.is_none() {
+                return Err(ResolveError::MissingAddress {
+                    location: instruction.source.clone(),
+                    name: key.1
+                });
             }
 
-            labels.insert(label, program[i].clone());
+            trace!("Found label at address {}: .{}", instruction.location.unwrap(), key.1);
+
+            labels.insert(key, instruction.clone());
         }
     }
     trace!("All {} labels found", labels.len());
     trace!("Labels: {:?}", labels.keys());
+
+    let exported = find_exports(program, &labels)?;
+    let imported = declared_names(program, Opcode::_Extern);
     trace!("Binding labels");
 
-    for i in 0..program.len() {
-        if program[i].opcode == _Label {
+    for (i, instruction) in program.iter_mut().enumerate() {
+        if instruction.opcode == _Label {
             continue;
         }
-        for j in 0..program[i].operands.len() {
-            let operand = &mut program[i].operands[j];
-            if let Operand::Label(label) = operand {
-                match labels.get(&label.name) {
-                    None => return Err(ResolveError::UnknownLabel(label.name.clone())),
-                    Some(bind) => label.set_address(bind)
+        let file = instruction.source.file.clone();
+        for (operand, location) in instruction.located_operands_mut() {
+            for label in operand.labels_mut() {
+                let key = (file.clone(), label.name.clone());
+                let bind = if is_anonymous(&label.name) {
+                    find_anonymous(&anonymous, i, &file, &label.name).map(|(_, label)| label)
+                } else if imported.contains_key(&key) {
+                    exported.get(&label.name).and_then(|(_, bind)| bind.as_ref())
+                } else {
+                    labels.get(&key)
+                };
+
+                match bind {
+                    Some(bind) => label.set_address(bind),
+                    None => {
+                        return match exported.get(&label.name) {
+                            Some((exporter, _)) if !imported.contains_key(&key) => {
+                                Err(ResolveError::NotImported {
+                                    location: location.clone(),
+                                    name: label.name.clone(),
+                                    exporter: exporter.clone()
+                                })
+                            },
+                            _ => {
+                                let visible = labels
+                                    .keys()
+                                    .chain(imported.keys())
+                                    .filter(|(f, _)| *f == file)
+                                    .map(|(_, name)| name);
+
+                                Err(ResolveError::UnknownLabel {
+                                    location: location.clone(),
+                                    name: label.name.clone(),
+                                    suggestion: Suggestion::closest(&label.name, visible)
+                                })
+                            }
+                        };
+                    }
                 }
             }
         }
     }
     trace!("All {} offsets bound", labels.len());
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Collects every name exported with `.global`, along with where it is exported and its
+/// label (definitions are exported too, but bound by the evaluator). Also checks that
+/// every `.extern` has a matching `.global`
+fn find_exports(program: &[Instruction], labels: &Symbols) -> Result<HashMap<String, (SourceLocation, Option<Instruction>)>, ResolveError> {
+    let mut exported: HashMap<String, (SourceLocation, Option<Instruction>)> = HashMap::new();
+    let definitions: HashSet<(String, String)> = program
+        .iter()
+        .filter(|i| i.opcode.is_definition())
+        .filter_map(|i| match &i.operands[0] {
+            Operand::Def(def) => Some((i.source.file.clone(), def.name.clone())),
+            _ => None
+        })
+        .collect();
+
+    for ((file, name), location) in declared_names(program, Opcode::_Global) {
+        let key = (file.clone(), name.clone());
+        let label = labels.get(&key).cloned();
+        if label.is_none() && !definitions.contains(&key) {
+            return Err(ResolveError::UndefinedGlobal { location, name });
+        }
+
+        if let Some((first, _)) = exported.get(&name) {
+            if first.file != file {
+                return Err(ResolveError::ConflictingGlobal {
+                    name,
+                    first: first.clone(),
+                    second: location
+                });
+            }
+        }
+        trace!("{} exports {}", file, name);
+        exported.insert(name, (location, label));
+    }
+
+    for ((file, name), location) in declared_names(program, Opcode::_Extern) {
+        let key = (file, name.clone());
+        if labels.contains_key(&key) || definitions.contains(&key) {
+            return Err(ResolveError::ConflictingExtern { location, name });
+        }
+        if !exported.contains_key(&name) {
+            return Err(ResolveError::UndefinedExtern { location, name });
+        }
+    }
+    Ok(exported)
+}
+
+/// Every (file, name) pair listed by a `.global` or `.extern` directive
+pub(crate) fn declared_names(program: &[Instruction], opcode: Opcode) -> Declarations {
+    program
+        .iter()
+        .filter(|i| i.opcode == opcode)
+        .flat_map(|i| {
+            i.operands
+                .iter()
+                .enumerate()
+                .map(move |(n, o)| ((i.source.file.clone(), o.to_string()), i.operand_source(n).clone()))
+        })
+        .collect()
+}
+
+/// Rewrites local labels (`..loop`) to their qualified name (`draw_cell.loop`)
+/// using the closest global label above them as the scope. Labels that the expander generates
+/// are unique in the whole program and stay as they are
+fn scope_local_labels(program: &mut [Instruction]) -> Result<(), ResolveError> {
+    let mut scope: Option<String> = None;
+    let mut file = String::new();
+    for instruction in program.iter_mut() {
+        if instruction.source.file != file {
+            file = instruction.source.file.clone();
+            scope = None;
+        }
+
+        if instruction.opcode == _Label {
+            if let Operand::Name(name) = &mut instruction.operands[0] {
+                if let Some(local) = name.strip_prefix('.').filter(|l| !is_generated(l)) {
+                    match &scope {
+                        Some(s) => *name = format!("{s}.{local}"),
+                        None => {
+                            return Err(ResolveError::OrphanLocalLabel {
+                                location: instruction.source.clone(),
+                                name: name.clone()
+                            });
+                        }
+                    }
+                } else if is_global_label(name) {
+                    scope = Some(name.clone());
+                }
+            }
+            continue;
+        }
+
+        for (operand, location) in instruction.located_operands_mut() {
+            for label in operand.labels_mut() {
+                if let Some(local) = label.name.strip_prefix('.').filter(|l| !is_generated(l)) {
+                    match &scope {
+                        Some(s) => label.name = format!("{s}.{local}"),
+                        None => {
+                            return Err(ResolveError::OrphanLocalLabel {
+                                location: location.clone(),
+                                name: label.name.clone()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Global labels open a new scope for local labels and local aliases
+pub(crate) fn is_global_label(name: &str) -> bool {
+    !name.starts_with('.') && !is_anonymous(name)
+}
+
+/// Generated labels have an `@`, which no source can write
+pub(crate) fn is_generated(name: &str) -> bool {
+    name.contains('@')
+}
+
+pub(crate) fn is_anonymous(name: &str) -> bool {
+    !name.is_empty() && (name.chars().all(|c| c == '+') || name.chars().all(|c| c == '-'))
+}
+
+/// `.+` is the next `.+` after the usage, `.++` the one after that, and so on.
+/// `.-` works the same way, but backwards. Anonymous labels never cross into another file
+pub(crate) fn find_anonymous<'a>(anonymous: &'a [(usize, Instruction)], usage: usize, file: &str, name: &str) -> Option<&'a (usize, Instruction)> {
+    let direction = &name[..1];
+    let nth = name.len() - 1;
+    let candidates = anonymous
+        .iter()
+        .filter(|(_, label)| label.operands[0].to_string() == direction && label.source.file == file);
+
+    if direction == "+" {
+        candidates.filter(|(i, _)| *i > usage).nth(nth)
+    } else {
+        candidates.filter(|(i, _)| *i < usage).rev().nth(nth)
+    }
+}
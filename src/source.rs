@@ -0,0 +1,37 @@
+use std::fmt::Display;
+
+/// Where an instruction came from, shown in errors as `file:line:column`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize
+}
+
+impl SourceLocation {
+    pub fn new(file: &str, line: usize, column: usize) -> SourceLocation {
+        SourceLocation {
+            file: file.to_string(),
+            line,
+            column
+        }
+    }
+
+    /// Finds the line and column (both starting at 1) of a byte offset into `contents`
+    pub fn from_offset(file: &str, contents: &str, offset: usize) -> SourceLocation {
+        let before = &contents[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        SourceLocation::new(file, line, column)
+    }
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "<unknown>")
+        } else {
+            write!(f, "{}:{}:{}", self.file, self.line, self.column)
+        }
+    }
+}
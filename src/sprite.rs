@@ -0,0 +1,200 @@
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+/// Width and height of the pixel display
+pub const SCREEN_SIZE: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum SpriteError {
+    #[error("Cannot read sprite {path}: {error}")]
+    Unreadable {
+        path: String,
+        error: std::io::Error
+    },
+    #[error("Sprite {path} is not a valid image: {reason}")]
+    Invalid {
+        path: String,
+        reason: String
+    },
+    #[error("Sprite {path} is {width}x{height} pixels, the screen is only {SCREEN_SIZE}x{SCREEN_SIZE}")]
+    TooLarge {
+        path: String,
+        width: usize,
+        height: usize
+    }
+}
+
+/// A black and white image for the pixel display. Set pixels are drawn, the rest is left alone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<bool>
+}
+
+impl Sprite {
+    /// Reads a PBM (`P1`, `P4`) or PGM (`P2`, `P5`) image, where dark pixels are set, or else
+    /// ASCII art, where every character but a space or a `.` is set
+    pub fn load(path: &Path) -> Result<Sprite, SpriteError> {
+        let name = path.display().to_string();
+        let bytes = fs::read(path).map_err(|error| SpriteError::Unreadable {
+            path: name.clone(),
+            error
+        })?;
+
+        let sprite = match bytes.get(..2) {
+            Some([b'P', b'1' | b'2' | b'4' | b'5']) => netpbm(&bytes),
+            _ => ascii_art(&bytes)
+        };
+        let sprite = sprite.map_err(|reason| SpriteError::Invalid {
+            path: name.clone(),
+            reason
+        })?;
+
+        if sprite.width > SCREEN_SIZE || sprite.height > SCREEN_SIZE {
+            return Err(SpriteError::TooLarge {
+                path: name,
+                width: sprite.width,
+                height: sprite.height
+            });
+        }
+        Ok(sprite)
+    }
+
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Every set pixel as x and y from the bottom left corner, row by row from the top. The image
+    /// goes from the top down, but y=0 is the bottom row of the screen
+    pub fn set_pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.height)
+            .flat_map(move |row| (0..self.width).map(move |x| (x, row)))
+            .filter(|(x, row)| self.is_set(*x, *row))
+            .map(|(x, row)| (x, self.height - 1 - row))
+    }
+}
+
+fn ascii_art(bytes: &[u8]) -> Result<Sprite, String> {
+    let text = std::str::from_utf8(bytes).map_err(|_| "ASCII art has to be text".to_string())?;
+    let mut rows: Vec<Vec<bool>> = text
+        .lines()
+        .map(|line| line.chars().map(|c| c != ' ' && c != '.').collect())
+        .collect();
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut pixels = Vec::with_capacity(width * rows.len());
+    for row in &rows {
+        pixels.extend(row);
+        pixels.extend(std::iter::repeat_n(false, width - row.len()));
+    }
+    Ok(Sprite {
+        width,
+        height: rows.len(),
+        pixels
+    })
+}
+
+/// The header is whitespace separated numbers with `#` comments, followed by the pixels as
+/// more numbers (`P1`, `P2`) or as binary right after one whitespace character (`P4`, `P5`)
+fn netpbm(bytes: &[u8]) -> Result<Sprite, String> {
+    let format = bytes[1];
+    let mut position = 2;
+    let width = next_number(bytes, &mut position, false)?;
+    let height = next_number(bytes, &mut position, false)?;
+    let maximum = match format {
+        b'2' | b'5' => next_number(bytes, &mut position, false)?,
+        _ => 1
+    };
+    if maximum == 0 || maximum > u16::MAX as usize {
+        return Err(format!("{maximum} is not a valid maximum gray value"));
+    }
+    // Gray values count as set when they are closer to black than to white
+    let dark = |value: usize| value * 2 < maximum;
+
+    let count = width * height;
+    let pixels: Vec<bool> = match format {
+        // P1 pixels do not need whitespace between them
+        b'1' => (0..count).map(|_| next_number(bytes, &mut position, true).map(|n| n == 1)).collect::<Result<_, _>>()?,
+        b'2' => (0..count).map(|_| next_number(bytes, &mut position, false).map(dark)).collect::<Result<_, _>>()?,
+        b'4' => {
+            let row_bytes = width.div_ceil(8);
+            let data = bytes.get(position + 1..position + 1 + row_bytes * height).ok_or("the file ends early")?;
+            (0..count)
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    data[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0
+                })
+                .collect()
+        },
+        _ => {
+            let sample = if maximum > 255 { 2 } else { 1 };
+            let data = bytes.get(position + 1..position + 1 + count * sample).ok_or("the file ends early")?;
+            data.chunks(sample)
+                .map(|s| dark(s.iter().fold(0, |value, b| value << 8 | *b as usize)))
+                .collect()
+        }
+    };
+
+    Ok(Sprite {
+        width,
+        height,
+        pixels
+    })
+}
+
+fn next_number(bytes: &[u8], position: &mut usize, single_digit: bool) -> Result<usize, String> {
+    loop {
+        match bytes.get(*position) {
+            Some(b'#') => {
+                while bytes.get(*position).is_some_and(|b| *b != b'\n') {
+                    *position += 1;
+                }
+            },
+            Some(b) if b.is_ascii_whitespace() => *position += 1,
+            Some(_) => break,
+            None => return Err("the file ends early".to_string())
+        }
+    }
+
+    let start = *position;
+    let digits = bytes[start..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let length = if single_digit { digits.min(1) } else { digits };
+    if length == 0 || length > 5 {
+        return Err(format!("expected a number at byte {start}"));
+    }
+    *position += length;
+    Ok(bytes[start..start + length].iter().fold(0, |n, b| n * 10 + (b - b'0') as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let arrow = Sprite::load(Path::new("./test_data/sprites/arrow.txt"));
+        assert!(arrow.is_ok(), "Failed to load sprite: {}", arrow.err().unwrap());
+        let arrow = arrow.unwrap();
+        assert_eq!((arrow.width, arrow.height), (5, 5));
+        assert_eq!(arrow.set_pixels().take(4).collect::<Vec<_>>(), [(2, 4), (1, 3), (2, 3), (3, 3)],
+            "The top row of the image is not at the top of the sprite");
+
+        for format in ["arrow.pbm", "arrow_raw.pbm", "arrow.pgm"] {
+            let sprite = Sprite::load(&Path::new("./test_data/sprites").join(format));
+            assert!(sprite.is_ok(), "Failed to load sprite: {}", sprite.err().unwrap());
+            assert_eq!(sprite.unwrap(), arrow, "{format} is not the same arrow as arrow.txt");
+        }
+    }
+
+    #[test]
+    fn ascii_art_rows() {
+        let sprite = ascii_art(b"#\n.##\n\n").unwrap();
+        assert_eq!((sprite.width, sprite.height), (3, 2), "Short rows were not padded or blank rows not dropped");
+        assert!(sprite.is_set(0, 0) && !sprite.is_set(1, 0) && !sprite.is_set(0, 1) && sprite.is_set(2, 1));
+    }
+}
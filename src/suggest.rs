@@ -0,0 +1,60 @@
+use std::fmt::Display;
+
+/// A close match for a misspelled name, shown after the error as `, did you mean draw_pixel?`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Suggestion(pub Option<String>);
+
+impl Suggestion {
+    /// Picks the candidate with the fewest edits from `name` (ignoring case), as long as it is
+    /// close enough to plausibly be a typo. Ties go to the alphabetically first candidate
+    pub fn closest<I, S>(name: &str, candidates: I) -> Suggestion
+    where I: IntoIterator<Item = S>, S: AsRef<str> {
+        let name = name.to_lowercase();
+        let limit = (name.chars().count() / 3).max(1);
+
+        let best = candidates
+            .into_iter()
+            .map(|c| c.as_ref().to_string())
+            .filter(|c| c.to_lowercase() != name)
+            .map(|c| (distance(&name, &c.to_lowercase()), c))
+            .filter(|(d, _)| *d <= limit)
+            .min();
+
+        Suggestion(best.map(|(_, c)| c))
+    }
+}
+
+impl Display for Suggestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.0 {
+            Some(name) => write!(f, ", did you mean {}?", name),
+            None => Ok(())
+        }
+    }
+}
+
+/// Edit distance counting inserted, deleted and replaced characters as well as swapped neighbours
+/// (`pixle` is one edit away from `pixel`)
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
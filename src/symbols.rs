@@ -0,0 +1,91 @@
+use crate::architecture::batpu2::instruction::Instruction;
+use crate::architecture::batpu2::opcode::Opcode;
+use crate::architecture::batpu2::operand::Operand;
+use crate::source::SourceLocation;
+
+/// Lists every label, variable and definition with its value, e.g. to find things in an emulator
+pub struct SymbolMap<'a> {
+    program: &'a [Instruction]
+}
+
+struct Symbol<'a> {
+    kind: String,
+    value: i32,
+    /// Labels are program memory addresses and variables data memory addresses
+    is_address: bool,
+    name: String,
+    source: &'a SourceLocation
+}
+
+impl<'a> SymbolMap<'a> {
+    pub fn new(program: &[Instruction]) -> SymbolMap<'_> {
+        SymbolMap {
+            program
+        }
+    }
+
+    pub fn print(&self) -> String {
+        let mut symbols: Vec<Symbol> = self.program
+            .iter()
+            .filter_map(symbol)
+            .collect();
+        symbols.sort_by_key(|s| (s.order(), s.value));
+
+        let mut output = String::from("\nkind       | value  | name                           | source\n");
+        output.push_str("---------- | ------ | ------------------------------ | ------\n");
+        for s in symbols {
+            let value = if s.is_address { format!("0x{:04X}", s.value) } else { s.value.to_string() };
+            output.push_str(&format!("{:<10} | {:<6} | {:<30} | {}\n", s.kind, value, s.name, s.source));
+        }
+        output
+    }
+}
+
+impl Symbol<'_> {
+    /// Labels first, then variables, then definitions
+    fn order(&self) -> u8 {
+        match self.kind.as_str() {
+            "label" => 0,
+            "variable" | "array" | "table" | "stack" => 1,
+            _ => 2
+        }
+    }
+}
+
+fn symbol(instruction: &Instruction) -> Option<Symbol<'_>> {
+    let (kind, value, is_address, name) = match (instruction.opcode, instruction.operands.first()) {
+        (Opcode::_Label, Some(Operand::Name(name))) => {
+            let address = (instruction.location?.value() << 1) as i32;
+            ("label".to_string(), address, true, format!(".{}", name))
+        },
+        (Opcode::_Var, Some(Operand::Def(def))) => {
+            ("variable".to_string(), def.value? as i32, true, def.name.clone())
+        },
+        (Opcode::_Array | Opcode::_Stack, Some(Operand::Def(def))) => {
+            let length = match instruction.operands.get(1) {
+                Some(Operand::Imm(length)) => length.value(),
+                _ => 0
+            };
+            let kind = if instruction.opcode == Opcode::_Stack { "stack" } else { "array" };
+            (kind.to_string(), def.value? as i32, true, format!("{} ({} bytes)", def.name, length))
+        },
+        (Opcode::_Table, Some(Operand::Def(def))) => {
+            let length = instruction.operands.len() - 1;
+            ("table".to_string(), def.value? as i32, true, format!("{} ({} bytes)", def.name, length))
+        },
+        // Struct fields and sizes are typed definitions too
+        (opcode, Some(Operand::Def(def))) if opcode.is_definition() => {
+            let kind = def.kind.map_or("definition".to_string(), |k| k.to_string());
+            (kind, def.value? as i32, false, def.name.clone())
+        },
+        _ => return None
+    };
+
+    Some(Symbol {
+        kind,
+        value,
+        is_address,
+        name,
+        source: &instruction.source
+    })
+}
@@ -0,0 +1,33 @@
+// Moves a cursor around using named registers
+
+alias cursor_x r1
+alias cursor_y r2
+alias input r14
+alias port r15
+
+    LDI cursor_x, 0
+    LDI cursor_y, 0
+.main_loop
+    LDI port, controller_input
+    LOD port, input
+    CAL .move
+    JMP .main_loop
+
+.move
+    alias local tmp r13
+    alias local mask r13 // same register as tmp, this warns
+    LDI mask, 1
+    AND input, mask, tmp
+    BRH eq, ..no_right
+    INC cursor_x
+..no_right
+    RET
+
+.draw
+    alias local tmp r12 // tmp is free again after .move
+    LDI port, pixel_x
+    STR port, cursor_x
+    LDI port, pixel_y
+    STR port, cursor_y
+    MOV tmp, cursor_x
+    RET
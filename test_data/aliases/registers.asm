@@ -0,0 +1,25 @@
+// Same program as aliases.asm, with plain registers
+
+    LDI r1, 0
+    LDI r2, 0
+.main_loop
+    LDI r15, controller_input
+    LOD r15, r14
+    CAL .move
+    JMP .main_loop
+
+.move
+    LDI r13, 1
+    AND r14, r13, r13
+    BRH eq, ..no_right
+    INC r1
+..no_right
+    RET
+
+.draw
+    LDI r15, pixel_x
+    STR r15, r1
+    LDI r15, pixel_y
+    STR r15, r2
+    MOV r12, r1
+    RET
@@ -0,0 +1,15 @@
+// Multiplication and division, unrolled for constants and calling shared routines otherwise
+define WIDTH 10
+alias divisor r3
+
+.main
+    LDI r1 23
+    LDI divisor 3
+    MUL r1, WIDTH, r2
+    MUL r1, 8
+    DIV r2, 4, r4
+    MOD r2, 16, r5
+    MUL r1, r2, r6
+    DIV r6, divisor, r7
+    MOD r6, 7, r8
+    HLT
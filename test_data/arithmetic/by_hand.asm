@@ -0,0 +1,78 @@
+// Both files written out, with the shared routines at the end
+.main
+    LDI r1 23
+    LDI r3 3
+    MOV r1 r14
+    LSH r14 r14
+    MOV r14 r2
+    LSH r14 r14
+    LSH r14 r14
+    ADD r2 r14 r2
+    LSH r1 r1
+    LSH r1 r1
+    LSH r1 r1
+    RSH r2 r4
+    RSH r4 r4
+    LDI r14 15
+    AND r2 r14 r5
+    MOV r1 r12
+    MOV r2 r13
+    CAL .multiply
+    MOV r12 r6
+    MOV r6 r12
+    MOV r3 r13
+    CAL .divide
+    MOV r12 r7
+    MOV r6 r12
+    LDI r13 7
+    CAL .divide
+    MOV r13 r8
+    HLT
+
+.square
+    MOV r9 r12
+    MOV r9 r13
+    CAL .multiply
+    MOV r12 r9
+    RET
+
+.multiply
+    MOV r12 r15
+    LDI r12 0
+..loop
+    LDI r14 1
+    AND r13 r14 r14
+    BRH zs ..skip
+    ADD r12 r15 r12
+..skip
+    LSH r15 r15
+    RSH r13 r13
+    CMP r13 r0
+    BRH zc ..loop
+    RET
+
+.divide
+    LDI r15 0
+    LDI r14 8
+..loop
+    LSH r15 r15
+    BRH cs ..overflow
+    LSH r12 r12
+    BRH cc ..compare
+    INC r15
+..compare
+    CMP r15 r13
+    BRH cc ..next
+..subtract
+    SUB r15 r13 r15
+    INC r12
+..next
+    DEC r14
+    BRH zc ..loop
+    MOV r15 r13
+    RET
+..overflow
+    LSH r12 r12
+    BRH cc ..subtract
+    INC r15
+    JMP ..subtract
@@ -0,0 +1,4 @@
+// Calls the multiply routine, which is emitted for the first file
+.square
+    MUL r9, r9
+    RET
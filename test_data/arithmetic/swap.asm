@@ -0,0 +1,5 @@
+// The arguments are each other's registers, so they are swapped through the scratch register
+.scratch r11
+.main
+    MUL r13, r12
+    HLT
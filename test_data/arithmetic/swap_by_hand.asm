@@ -0,0 +1,8 @@
+// swap.asm with the swap written out, the operands are then where the routine takes them
+.main
+    MOV r13 r11
+    MOV r12 r13
+    MOV r11 r12
+    MUL r12, r13
+    MOV r12 r13
+    HLT
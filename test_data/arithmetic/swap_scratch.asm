@@ -0,0 +1,4 @@
+.scratch r12
+.main
+    MUL r13, r12
+    HLT
@@ -0,0 +1,3 @@
+.main
+    DIV r1, 0
+    HLT
@@ -0,0 +1,20 @@
+// The same as mnemonics.asm, written with BRH
+.main
+    LDI r1 5
+    LDI r2 0
+    LDI r3 10
+.down
+    DEC r1
+    BRH ne .down
+    BRH zs .up
+.up
+    INC r2
+    CMP r2 r3
+    BRH lt .up
+    BRH cc .up
+    BRH ge .done
+    BRH nz 0x0014
+    BRH cs .done
+.done
+    BRH eq .main
+    HLT
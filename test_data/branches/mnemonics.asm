@@ -0,0 +1,20 @@
+// Counts r1 down to zero and r2 up to ten with the branch mnemonics
+.main
+    LDI r1 5
+    LDI r2 0
+    LDI r3 10
+.down
+    DEC r1
+    BNE .down
+    BZ .up
+.up
+    INC r2
+    CMP r2 r3
+    BLT .up
+    bcc .up
+    BGE .done
+    BNZ $ - 2
+    bcs .done
+.done
+    BEQ .main
+    HLT
@@ -0,0 +1,6 @@
+// The character display has no '#'
+
+LDI r15, write_char
+LDI r14, '#'
+STR r15, r14
+HLT
@@ -0,0 +1,54 @@
+// The same program with branches and labels
+.main
+    LDI r15 pixel_x
+    LDI r3 16
+    LDI r2 0
+    JMP .rows_test
+.rows
+    STR r15 r2 1
+    LDI r1 0
+    JMP .columns_test
+.columns
+    STR r15 r1
+    XOR r1 r2 r5
+    LDI r14 1
+    AND r5 r14 r5
+    BRH nz .odd
+    STR r15 r0 2
+    JMP .drawn
+.odd
+    STR r15 r0 3
+.drawn
+    INC r1
+.columns_test
+    CMP r1 r3
+    BRH nc .columns
+    INC r2
+.rows_test
+    LDI r14 32
+    CMP r2 r14
+    BRH nz .rows
+    STR r15 r0 5
+
+    LDI r4 10
+.count
+    LDI r14 5
+    CMP r14 r4
+    BRH c .small
+    INC r6
+.small
+    CMP r3 r4
+    BRH nc .next
+    LDI r14 3
+    CMP r6 r14
+    BRH nc .few
+    DEC r6
+    JMP .next
+.few
+    CMP r6 r0
+    BRH nz .next
+    LDI r7 'A'
+.next
+    DEC r4
+    BRH nz .count
+    HLT
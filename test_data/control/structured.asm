@@ -0,0 +1,46 @@
+// Draws a checkerboard, then counts down with every kind of block
+alias x r1
+alias y r2
+alias limit r3
+alias steps r4
+define SIZE 32
+
+.main
+    LDI r15 pixel_x
+    LDI limit 16
+    LDI y 0
+    .while y != SIZE
+        STR r15 y 1
+        LDI x 0
+        .while x < limit
+            STR r15 x
+            XOR x y r5
+            LDI r14 1
+            AND r5 r14 r5
+            .if zero
+                STR r15 r0 2
+            .else
+                STR r15 r0 3
+            .endif
+            INC x
+        .endwhile
+        INC y
+    .endwhile
+    STR r15 r0 5
+
+    LDI steps 10
+    .loop steps
+        .if steps > 5
+            INC r6
+        .endif
+        .if steps <= limit
+            .if r6 >= 3
+                DEC r6
+            .else
+                .if r6 == 0
+                    LDI r7 'A'
+                .endif
+            .endif
+        .endif
+    .endloop
+    HLT
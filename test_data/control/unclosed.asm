@@ -0,0 +1,7 @@
+// The .if is still open when the loop ends
+.main
+    .while r1 < r2
+        .if r1 == 0
+            INC r1
+    .endwhile
+    HLT
@@ -0,0 +1,5 @@
+// Nothing to close
+.main
+    INC r1
+    .endloop
+    HLT
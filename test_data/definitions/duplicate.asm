@@ -0,0 +1,9 @@
+// A definition can only be given one value
+
+define ROWS 20
+define COLUMNS 10
+define ROWS 24
+
+.main
+    LDI r1, ROWS
+    HLT
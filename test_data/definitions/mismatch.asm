@@ -0,0 +1,7 @@
+// An address is not an immediate
+
+define START:addr 512
+
+.main
+    LDI r1, START
+    HLT
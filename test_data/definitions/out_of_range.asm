@@ -0,0 +1,7 @@
+// Untyped definitions are checked where they are used, offsets only go from -8 to 7
+
+define STRIDE 12
+
+.main
+    STR r1, r2, STRIDE
+    HLT
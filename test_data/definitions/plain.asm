@@ -0,0 +1,8 @@
+// Hand-written version of typed.asm
+
+.main
+    LDI r3, 20
+    LDI r4, 246
+    STR r4, r0
+    LOD r5, r3, -2
+    JMP 0x0004
@@ -0,0 +1,14 @@
+// Typed definitions, assembled to the same binary as plain.asm
+
+define ROWS:imm 20
+define NEXT_ROW:offset -2
+define SCREEN:port clear_screen_buffer
+define START:addr 4
+define row:reg r3
+
+.main
+    LDI row, ROWS
+    LDI r4, SCREEN
+    STR r4, r0
+    LOD r5, row, NEXT_ROW
+    JMP START
@@ -0,0 +1,16 @@
+// Labels named after directives that take operands, nothing follows them so they stay labels
+
+.main
+    CAL .print
+    CAL .fill
+    JMP .stack
+.print
+    LDI r1, 1
+    RET
+.fill   // a comment is not an operand
+    .fill 2, INC r1
+    RET
+.stack
+    .print "Done"
+.error
+    HLT
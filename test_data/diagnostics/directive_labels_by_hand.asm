@@ -0,0 +1,17 @@
+// directive_labels.asm with labels that are not directive names
+
+.main
+    CAL .show
+    CAL .repeat
+    JMP .finish
+.show
+    LDI r1, 1
+    RET
+.repeat
+    INC r1
+    INC r1
+    RET
+.finish
+    .print "Done"
+.failed
+    HLT
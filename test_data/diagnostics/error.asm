@@ -0,0 +1,5 @@
+// Programs can stop the build on their own
+
+.start
+    HLT
+.error "Not implemented yet"
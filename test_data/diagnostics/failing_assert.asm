@@ -0,0 +1,9 @@
+// The grid is too large
+
+define ROW_COUNT 9
+
+.start
+    LDI r1, ROW_COUNT
+    HLT
+
+.assert ROW_COUNT * 2 < 16, "Rows do not fit in the grid"
@@ -0,0 +1,23 @@
+// Invariants the program checks about itself
+
+define ROW_COUNT 6
+define TABLE_SIZE 4
+
+.assert ROW_COUNT * 2 < 16, "Rows do not fit in the grid"
+.assert (ROW_COUNT << 1) == 12
+.warning "Table layout is still a draft"
+
+.start
+    LDI r1, ROW_COUNT
+    JMP .end
+
+.table
+    NOP
+    NOP
+    NOP
+    NOP
+.end
+    HLT
+
+.assert .end - .table == TABLE_SIZE * 2, "Table has the wrong size"
+.assert .end < PIXEL_X, "Program reaches the I/O ports"
@@ -0,0 +1,5 @@
+// .else has no operands, so as a label it would be taken for the directive
+
+.main
+    JMP .else
+    HLT
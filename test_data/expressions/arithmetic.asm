@@ -0,0 +1,14 @@
+// Jumps using the location counter and label arithmetic
+
+.start
+    LDI r1, 3
+    JMP $ + 4
+    HLT
+    BRH eq, .table + 2
+    CAL .table+0x4
+    JMP .start - $ + 0x10
+
+.table
+    NOP
+    NOP
+    RET
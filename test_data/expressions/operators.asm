@@ -0,0 +1,16 @@
+// Same program as arithmetic.asm, using definitions, parentheses and the other operators
+
+define SKIP 2
+
+.start
+    LDI r1, 3
+    JMP $ + (SKIP << 1)
+    HLT
+    BRH eq, .table + 8 / 4
+    CAL .table + -(-4) + ~-1
+    JMP (.start - $ + 0x10) & 0xFF
+
+.table
+    NOP
+    NOP
+    RET
@@ -0,0 +1,4 @@
+// Jumps to an address that does not exist
+
+.start
+    JMP .start - 2
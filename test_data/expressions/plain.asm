@@ -0,0 +1,17 @@
+// Same program as arithmetic.asm, with every address written out
+
+.start
+    LDI r1, 3
+    JMP .skip
+    HLT
+.skip
+    BRH eq, .second
+    CAL .third
+    JMP 0x0006
+
+.table
+    NOP
+.second
+    NOP
+.third
+    RET
@@ -0,0 +1,16 @@
+// The same as pseudo.asm, written out by hand
+.main
+    LDI r1 200
+    LDI r14 100
+    CMP r1 r14
+    BGE .big
+    LDI r14 3
+    SUB r1 r14 r1
+    LDI r14 15
+    AND r1 r14 r2
+.big
+    LDI r13 'A'
+    XOR r1 r13 r3
+    LDI r13 pixel_x
+    CMP r2 r13
+    HLT
@@ -0,0 +1,15 @@
+// Immediate operands for the ALU and compares, through the scratch register
+define MASK 0x0F
+alias value r1
+
+.main
+    LDI value 200
+    CMPI value, 100
+    BGE .big
+    SUBI value, 3
+    ANDI value MASK r2
+.big
+    .scratch r13
+    XORI r1, 'A', r3
+    cmpi r2 pixel_x
+    HLT
@@ -0,0 +1,17 @@
+// Hand-expanded version of custom.asm
+
+.main
+    LDI r1, 5
+    XOR r2, r2, r2
+..count
+    INC r2
+    DEC r1
+    ADD r0, r1, r0
+    BNE ..count
+    LOD r2, r3, 1
+    LOD r2, r4, 2
+    LOD r2, r4, 0
+    MOV r3, r5
+    XOR r4, r4, r4
+    XOR r4, r4, r4
+    HLT
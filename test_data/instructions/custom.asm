@@ -0,0 +1,16 @@
+// Uses the instruction aliases in project.cfg, same binary as by_hand.asm
+
+.main
+    LDI r1, 5
+    clr r2
+..count
+    INC r2
+    DEC r1
+    tst r1
+    BNE ..count
+    peek r2, r3
+    fetch r2, r4, 2
+    fetch r2, r4
+    copy r3, r5
+    .fill 2, clr r4
+    HLT
@@ -0,0 +1,24 @@
+// The same as pinned.asm, written out by hand
+.main
+    CAL .draw
+    HLT
+    NOP
+    NOP
+    NOP
+    NOP
+    NOP
+    NOP
+.draw
+    LDI r1 1
+    RET
+    HLT
+    HLT
+    HLT
+    HLT
+    HLT
+    HLT
+.update
+    INC r1
+    INC r1
+    INC r1
+    RET
@@ -0,0 +1,8 @@
+.main
+    LDI r1 1
+    LDI r2 2
+    LDI r3 3
+
+.org 0x0004
+.late
+    HLT
@@ -0,0 +1,17 @@
+// Routines pinned to fixed addresses, with the gaps filled in
+.main
+    CAL .draw
+    HLT
+
+.fill 2, NOP
+
+.align 16
+.draw
+    .filler HLT
+    LDI r1 1
+    RET
+
+.org 0x0020
+.update
+    .fill 3, INC r1
+    RET
@@ -0,0 +1,6 @@
+// Pseudo-instructions repeated by .fill expand every time
+.main
+    LDI r1 1
+    .align 8
+    .fill 2, SUBI r2, 3
+    HLT
@@ -0,0 +1,11 @@
+// The same as pseudo_fill.asm, written out by hand
+.main
+    LDI r1 1
+    NOP
+    NOP
+    NOP
+    LDI r14 3
+    SUB r2 r14 r2
+    LDI r14 3
+    SUB r2 r14 r2
+    HLT
@@ -0,0 +1,6 @@
+// A gap is filled one instruction at a time
+.main
+    .filler CMPI r1, 5
+    LDI r1 1
+    .align 8
+    HLT
@@ -0,0 +1,7 @@
+.main
+    JMP .end
+
+.org 0x07FE
+.end
+    HLT
+    HLT
@@ -0,0 +1,18 @@
+// Fills a rectangle and shows 6 * 7 / 10 with the standard library
+.include "std"
+
+.main
+    LDI r1 3
+    LDI r2 5
+    LDI r3 8
+    LDI r4 4
+    CAL .fill_rect
+    LDI r15 buffer_screen
+    STR r15 r0
+    LDI r1 6
+    LDI r2 7
+    CAL .multiply
+    LDI r2 10
+    CAL .divide
+    CAL .print_number
+    HLT
@@ -0,0 +1,108 @@
+// The same program with the standard library modules it calls written out, in the order of
+// the library, and the multiply and divide routines at the end
+.main
+    LDI r1 3
+    LDI r2 5
+    LDI r3 8
+    LDI r4 4
+    CAL .fill_rect
+    LDI r15 buffer_screen
+    STR r15 r0
+    LDI r1 6
+    LDI r2 7
+    CAL .multiply
+    LDI r2 10
+    CAL .divide
+    CAL .print_number
+    HLT
+
+.print_number
+    LDI r15 show_number
+    STR r15 r0 3        // unsigned_mode
+    STR r15 r1
+    RET
+
+.fill_rect
+    LDI r15 pixel_x
+    ADD r1 r3 r5        // r5 is the right edge
+    DEC r5
+    ADD r2 r4 r6        // r6 is the bottom edge
+    DEC r6
+    MOV r2 r7
+..row
+    STR r15 r7 1
+    MOV r1 r8
+..pixel
+    STR r15 r8
+    STR r15 r0 2        // draw_pixel
+    CMP r8 r5
+    BRH eq ..next
+    INC r8
+    JMP ..pixel
+..next
+    CMP r7 r6
+    BRH eq ..done
+    INC r7
+    JMP ..row
+..done
+    RET
+
+.multiply
+    MOV r1 r12
+    MOV r2 r13
+    CAL .multiply_routine
+    MOV r12 r1
+    RET
+
+.divide
+    MOV r1 r12          // the remainder
+    MOV r2 r13
+    CAL .divide_routine
+    MOV r13 r3
+    MOV r1 r12          // the quotient
+    MOV r2 r13
+    CAL .divide_routine
+    MOV r12 r1
+    MOV r3 r2
+    RET
+
+.multiply_routine
+    MOV r12 r15
+    LDI r12 0
+..loop
+    LDI r14 1
+    AND r13 r14 r14
+    BRH eq ..skip
+    ADD r12 r15 r12
+..skip
+    LSH r15 r15
+    RSH r13 r13
+    CMP r13 r0
+    BRH ne ..loop
+    RET
+
+.divide_routine
+    LDI r15 0
+    LDI r14 8
+..loop
+    LSH r15 r15
+    BRH ge ..overflow
+    LSH r12 r12
+    BRH lt ..compare
+    INC r15
+..compare
+    CMP r15 r13
+    BRH lt ..next
+..subtract
+    SUB r15 r13 r15
+    INC r12
+..next
+    DEC r14
+    BRH ne ..loop
+    MOV r15 r13
+    RET
+..overflow
+    LSH r12 r12
+    BRH lt ..subtract
+    INC r15
+    JMP ..subtract
@@ -0,0 +1,22 @@
+// Uses a few routines of the standard library, the rest is left out of the binary
+.include "std"
+
+.main
+    CAL .clear_screen
+    LDI r1 2
+    LDI r2 3
+    LDI r3 29
+    LDI r4 11
+    CAL .draw_line
+    LDI r1 4
+    LDI r2 20
+    LDI r3 10
+    LDI r4 6
+    CAL .draw_rect
+    LDI r15 buffer_screen
+    STR r15 r0
+    CAL .wait_input
+    LDI r1 6
+    CAL .random_range
+    CAL .print_number
+    HLT
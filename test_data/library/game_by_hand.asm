@@ -0,0 +1,150 @@
+// The same program with the standard library modules it calls written out, in the order of
+// the library, and the divide routine that MOD calls at the end
+.main
+    CAL .clear_screen
+    LDI r1 2
+    LDI r2 3
+    LDI r3 29
+    LDI r4 11
+    CAL .draw_line
+    LDI r1 4
+    LDI r2 20
+    LDI r3 10
+    LDI r4 6
+    CAL .draw_rect
+    LDI r15 buffer_screen
+    STR r15 r0
+    CAL .wait_input
+    LDI r1 6
+    CAL .random_range
+    CAL .print_number
+    HLT
+
+.print_number
+    LDI r15 show_number
+    STR r15 r0 3        // unsigned_mode
+    STR r15 r1
+    RET
+
+.clear_screen
+    LDI r15 clear_screen_buffer
+    STR r15 r0
+    STR r15 r0 -1       // buffer_screen
+    RET
+
+.draw_line
+    LDI r15 pixel_x
+    LDI r14 128         // sign bit
+    LDI r7 1            // r7 steps x, r5 is the distance
+    SUB r3 r1 r5
+    BRH ge ..right
+    LDI r7 -1
+    SUB r1 r3 r5
+..right
+    LDI r8 1            // r8 steps y, r6 is the distance
+    SUB r4 r2 r6
+    BRH ge ..down
+    LDI r8 -1
+    SUB r2 r4 r6
+..down
+    SUB r5 r6 r9        // r9 is the error, between -r6 and r5
+..plot
+    STR r15 r1
+    STR r15 r2 1
+    STR r15 r0 2        // draw_pixel
+    CMP r1 r3
+    BRH ne ..step
+    CMP r2 r4
+    BRH eq ..done
+..step
+    ADD r9 r9 r10       // Step x when twice the error is at least -r6
+    ADD r10 r6 r10
+    AND r10 r14 r0
+    BRH ne ..vertical
+    SUB r9 r6 r9
+    ADD r1 r7 r1
+..vertical
+    SUB r5 r10 r10      // Step y when twice the error is at most r5, r10 still has r6 added
+    ADD r10 r6 r10
+    AND r10 r14 r0
+    BRH ne ..plot
+    ADD r9 r5 r9
+    ADD r2 r8 r2
+    JMP ..plot
+..done
+    RET
+
+.draw_rect
+    LDI r15 pixel_x
+    ADD r1 r3 r5        // r5 is the right edge
+    DEC r5
+    ADD r2 r4 r6        // r6 is the bottom edge
+    DEC r6
+    MOV r1 r7
+..row
+    STR r15 r7
+    STR r15 r2 1
+    STR r15 r0 2        // draw_pixel
+    STR r15 r6 1
+    STR r15 r0 2
+    CMP r7 r5
+    BRH eq ..columns
+    INC r7
+    JMP ..row
+..columns
+    MOV r2 r7
+..column
+    STR r15 r7 1
+    STR r15 r1
+    STR r15 r0 2
+    STR r15 r5
+    STR r15 r0 2
+    CMP r7 r6
+    BRH eq ..done
+    INC r7
+    JMP ..column
+..done
+    RET
+
+.wait_input
+    LDI r15 controller_input
+..wait
+    LOD r15 r1
+    CMP r1 r0
+    BRH eq ..wait
+    RET
+
+.random_range
+    LDI r15 rng
+    LOD r15 r2
+    MOV r2 r12
+    MOV r1 r13
+    CAL .divide_routine
+    MOV r13 r1
+    RET
+
+.divide_routine
+    LDI r15 0
+    LDI r14 8
+..loop
+    LSH r15 r15
+    BRH ge ..overflow
+    LSH r12 r12
+    BRH lt ..compare
+    INC r15
+..compare
+    CMP r15 r13
+    BRH lt ..next
+..subtract
+    SUB r15 r13 r15
+    INC r12
+..next
+    DEC r14
+    BRH ne ..loop
+    MOV r15 r13
+    RET
+..overflow
+    LSH r12 r12
+    BRH lt ..subtract
+    INC r15
+    JMP ..subtract
@@ -0,0 +1,3 @@
+.include "maths"
+.main
+    HLT
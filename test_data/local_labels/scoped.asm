@@ -0,0 +1,28 @@
+// Two routines that both use .loop and .done thanks to local labels
+// and a few anonymous labels for short jumps
+
+.count_up
+    LDI r1, 0
+    LDI r2, 10
+..loop
+    CMP r1, r2
+    BRH eq, ..done
+    INC r1
+    JMP ..loop
+..done
+    CAL .count_down
+
+.count_down
+    LDI r3, 10
+..loop
+    DEC r3
+    BRH eq, .++
+    JMP ..loop
+.+
+    BRH ne, .count_down.done
+.-
+    NOP
+.+
+    JMP .-
+..done
+    HLT
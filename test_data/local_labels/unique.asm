@@ -0,0 +1,27 @@
+// Same program as scoped.asm, but with every label written out by hand
+
+.count_up
+    LDI r1, 0
+    LDI r2, 10
+.count_up_loop
+    CMP r1, r2
+    BRH eq, .count_up_done
+    INC r1
+    JMP .count_up_loop
+.count_up_done
+    CAL .count_down
+
+.count_down
+    LDI r3, 10
+.count_down_loop
+    DEC r3
+    BRH eq, .anonymous_3
+    JMP .count_down_loop
+.anonymous_1
+    BRH ne, .count_down_done
+.anonymous_2
+    NOP
+.anonymous_3
+    JMP .anonymous_2
+.count_down_done
+    HLT
@@ -0,0 +1,6 @@
+// r16 does not exist
+
+.main
+    LDI r1, 3
+    MOV r2, r16
+    HLT
@@ -0,0 +1,7 @@
+// The error should point at the second operand
+
+alias counter r1
+
+.main
+    ADD counter, countr, r2
+    HLT
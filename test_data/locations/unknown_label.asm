@@ -0,0 +1,8 @@
+// The error should point at the misspelled label, not just the file
+
+.main
+    LDI r1, 3
+.loop
+    DEC r1
+    BRH ne, .lopo
+    HLT
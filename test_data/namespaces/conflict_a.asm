@@ -0,0 +1,6 @@
+// Exports .draw, just like conflict_b.asm
+
+.global draw
+
+.draw
+    RET
@@ -0,0 +1,6 @@
+// Exports .draw, just like conflict_a.asm
+
+.global draw
+
+.draw
+    HLT
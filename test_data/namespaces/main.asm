@@ -0,0 +1,13 @@
+// Uses a routine and a definition from score.asm
+
+.extern add_score, POINTS
+
+.main
+    LDI r1, 0
+    LDI r2, 3
+.loop
+    CAL .add_score
+    DEC r2
+    BRH ne, .loop
+    LDI r3, POINTS
+    HLT
@@ -0,0 +1,13 @@
+// Has its own .loop, which does not clash with the one in main.asm
+
+.global add_score, POINTS
+
+define POINTS 10
+
+.add_score
+    LDI r4, 2
+.loop
+    ADI r1, POINTS
+    DEC r4
+    BRH ne, .loop
+    RET
@@ -0,0 +1,26 @@
+// The same program without register pairs
+.main
+    LDI r1 0
+    LDI r2 0
+    LDI r3 3
+    LDI r4 232
+.loop
+    ADD r1 r3 r1
+    ADD r2 r4 r2
+    BRH nc .add
+    INC r1
+.add
+    INC r4
+    BRH nz .inc
+    INC r3
+.inc
+    CMP r1 r5
+    BRH nz .cmp
+    CMP r2 r6
+.cmp
+    SUB r7 r1 r9
+    SUB r8 r2 r10
+    BRH c .sub
+    DEC r9
+.sub
+    HLT
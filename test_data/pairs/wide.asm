@@ -0,0 +1,14 @@
+// 16-bit counting with register pairs
+.pair total r1, r2
+.pair step r3, r4
+define LIMIT 250
+
+.main
+    LDI16 total 0
+    LDI16 step LIMIT * 4
+.loop
+    ADD16 total, step
+    INC16 step
+    CMP16 total, r5:r6
+    SUB16 r7:r8, total, r9:r10
+    HLT
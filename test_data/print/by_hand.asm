@@ -0,0 +1,26 @@
+// Same program as game_over.asm, written out by hand
+
+    LDI r3, 42
+    LDI r12, write_char
+    STR r12, r0, 2
+    LDI r13, "G"
+    STR r12, r13
+    LDI r13, "A"
+    STR r12, r13
+    LDI r13, "M"
+    STR r12, r13
+    LDI r13, "E"
+    STR r12, r13
+    STR r12, r0
+    LDI r13, "O"
+    STR r12, r13
+    LDI r13, "V"
+    STR r12, r13
+    LDI r13, "E"
+    STR r12, r13
+    LDI r13, "R"
+    STR r12, r13
+    STR r12, r0, 1
+    LDI r12, show_number
+    STR r12, r3
+    HLT
@@ -0,0 +1,9 @@
+// Prints "GAME OVER" and the score in r3
+
+.scratch r13
+.port_base r12
+
+    LDI r3, 42
+    .print "GAME OVER"
+    .print_reg_number r3
+    HLT
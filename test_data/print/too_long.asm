@@ -0,0 +1,4 @@
+// Does not fit on the display
+
+    .print "HELLO WORLD"
+    HLT
@@ -0,0 +1,11 @@
+// Three syntax errors and a label that does not exist, all reported in one run
+
+.main
+    LDI r1, 3
+    MOV r2, r16
+    ADD r1 r2
+.loop
+    DEC r1
+    BRH ne, .lop
+    FOO r1, r2
+    JMP .loop
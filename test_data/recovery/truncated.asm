@@ -0,0 +1,26 @@
+// Parsing stops before the label the first line jumps to
+
+.main
+    JMP .end
+    LDI r99, 1
+    LDI r99, 2
+    LDI r99, 3
+    LDI r99, 4
+    LDI r99, 5
+    LDI r99, 6
+    LDI r99, 7
+    LDI r99, 8
+    LDI r99, 9
+    LDI r99, 10
+    LDI r99, 11
+    LDI r99, 12
+    LDI r99, 13
+    LDI r99, 14
+    LDI r99, 15
+    LDI r99, 16
+    LDI r99, 17
+    LDI r99, 18
+    LDI r99, 19
+    LDI r99, 20
+.end
+    HLT
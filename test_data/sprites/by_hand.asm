@@ -0,0 +1,80 @@
+// The sprites of sprites.asm drawn by hand, y counts up from the bottom so the top row goes first
+.main
+    // arrow.txt at (3, 4), its top row at y = 8
+    LDI r15 pixel_x
+    LDI r14 5
+    STR r15 r14
+    ADI r14 3
+    STR r15 r14 1
+    STR r15 r0 2
+    ADI r14 -4
+    STR r15 r14
+    ADI r14 3
+    STR r15 r14 1
+    STR r15 r0 2
+    ADI r14 -2
+    STR r15 r14
+    STR r15 r0 2
+    INC r14
+    STR r15 r14
+    STR r15 r0 2
+    ADI r14 -3
+    STR r15 r14
+    ADI r14 3
+    STR r15 r14 1
+    STR r15 r0 2
+    DEC r14
+    STR r15 r14
+    STR r15 r0 2
+    ADI r14 2
+    STR r15 r14
+    STR r15 r0 2
+    ADI r14 -2
+    STR r15 r14
+    STR r15 r14 1
+    STR r15 r0 2
+    DEC r14
+    STR r15 r14 1
+    STR r15 r0 2
+
+    LDI r1 20
+    LDI r2 10
+    // arrow.pbm at (r1, r2)
+    LDI r15 pixel_x
+    ADI r1 2
+    STR r15 r1
+    ADI r2 4
+    STR r15 r2 1
+    STR r15 r0 2
+    DEC r1
+    STR r15 r1
+    DEC r2
+    STR r15 r2 1
+    STR r15 r0 2
+    INC r1
+    STR r15 r1
+    STR r15 r0 2
+    INC r1
+    STR r15 r1
+    STR r15 r0 2
+    ADI r1 -3
+    STR r15 r1
+    DEC r2
+    STR r15 r2 1
+    STR r15 r0 2
+    ADI r1 2
+    STR r15 r1
+    STR r15 r0 2
+    ADI r1 2
+    STR r15 r1
+    STR r15 r0 2
+    ADI r1 -2
+    STR r15 r1
+    DEC r2
+    STR r15 r2 1
+    STR r15 r0 2
+    DEC r2
+    STR r15 r2 1
+    STR r15 r0 2
+    ADI r1 -2
+    HLT 
@@ -0,0 +1,4 @@
+// Too far right for a 5 pixel wide sprite
+.main
+    .sprite "arrow.txt", 28, 0
+    HLT
@@ -0,0 +1,7 @@
+// Draws the same arrow at a fixed position and at a position held in registers
+.main
+    .sprite "arrow.txt", 3, 4
+    LDI r1 20
+    LDI r2 10
+    .sprite "arrow.pbm", r1, r2
+    HLT
@@ -0,0 +1,54 @@
+// The same as stack.asm, written out by hand
+.main
+    LDI r12 32
+    LDI r1 3
+    STR r12 r1 -1
+    DEC r12
+    STR r12 r2 -1
+    STR r12 r3 -2
+    STR r12 r4 -3
+    ADI r12 -3
+    CAL .work
+    LOD r12 r4
+    LOD r12 r3 1
+    LOD r12 r2 2
+    ADI r12 3
+    LOD r12 r1
+    INC r12
+    HLT
+
+.work
+    STR r12 r1 -1
+    STR r12 r2 -2
+    STR r12 r3 -3
+    STR r12 r4 -4
+    STR r12 r5 -5
+    STR r12 r6 -6
+    STR r12 r7 -7
+    STR r12 r8 -8
+    ADI r12 -8
+    STR r12 r9 -1
+    STR r12 r10 -2
+    STR r12 r11 -3
+    STR r12 r13 -4
+    STR r12 r14 -5
+    STR r12 r15 -6
+    ADI r12 -6
+    LDI r2 7
+    LOD r12 r15
+    LOD r12 r14 1
+    LOD r12 r13 2
+    LOD r12 r11 3
+    LOD r12 r10 4
+    LOD r12 r9 5
+    ADI r12 6
+    LOD r12 r8
+    LOD r12 r7 1
+    LOD r12 r6 2
+    LOD r12 r5 3
+    LOD r12 r4 4
+    LOD r12 r3 5
+    LOD r12 r2 6
+    LOD r12 r1 7
+    ADI r12 8
+    RET
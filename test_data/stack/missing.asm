@@ -0,0 +1,3 @@
+.main
+    PUSH r1
+    HLT
@@ -0,0 +1,19 @@
+// Saves registers on a software stack around calls
+.stack r12, 32
+alias counter r1
+define stack 7     // the stack itself has no name in the source
+
+.main
+    LDI counter 3
+    PUSH counter
+    PUSHALL r2, r3, r4
+    CAL .work
+    POPALL r2, r3, r4
+    POP counter
+    HLT
+
+.work
+    pushall
+    LDI r2 stack
+    popall
+    RET
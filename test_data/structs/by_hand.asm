@@ -0,0 +1,10 @@
+// Hand-written version of piece.asm
+
+.main
+    LDI r1, 0
+    LOD r1, r2, 0
+    LOD r1, r3, 1
+    STR r1, r0, 6
+    ADI r1, 7
+    LOD r1, r2, 2
+    HLT
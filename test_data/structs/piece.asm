@@ -0,0 +1,19 @@
+// Struct fields as LOD/STR offsets, same binary as by_hand.asm
+
+.struct Piece
+    .field x
+    .field y
+    .field cells 4
+    .field rotation
+.endstruct
+
+.array pieces 14
+
+.main
+    LDI r1, pieces
+    LOD r1, r2, Piece.x
+    LOD r1, r3, Piece.y
+    STR r1, r0, Piece.rotation
+    ADI r1, Piece.size
+    LOD r1, r2, Piece.cells
+    HLT
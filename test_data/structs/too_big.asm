@@ -0,0 +1,11 @@
+// The last field starts past the largest offset
+
+.struct Player
+    .field name 6
+    .field lives
+    .field score
+    .field level
+.endstruct
+
+.main
+    HLT
@@ -0,0 +1,7 @@
+// Typo in a definition name
+
+define GRID_WIDTH 10
+
+.main
+    LDI r1, GRID_WIDHT
+    HLT
@@ -0,0 +1,7 @@
+// Typo in a label name
+
+.main
+    CAL .draw_pixle
+    HLT
+.draw_pixel
+    RET
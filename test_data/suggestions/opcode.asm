@@ -0,0 +1,6 @@
+// Typo in an opcode
+
+.main
+    LDI r1, 3
+    MVO r2, r1
+    HLT
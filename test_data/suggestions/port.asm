@@ -0,0 +1,5 @@
+// Typo in a port name
+
+.main
+    LDI r1, clear_screne_buffer
+    HLT
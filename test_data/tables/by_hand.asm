@@ -0,0 +1,67 @@
+// The same as tables.asm, written out by hand
+define LINES 4
+
+.main
+    LDI r15 8
+    STR r15 r0 -8
+    LDI r14 1
+    STR r15 r14 -7
+    LDI r14 4
+    STR r15 r14 -6
+    LDI r14 9
+    STR r15 r14 -5
+    LDI r14 16
+    STR r15 r14 -4
+    LDI r14 25
+    STR r15 r14 -3
+    LDI r14 36
+    STR r15 r14 -2
+    LDI r14 49
+    STR r15 r14 -1
+    LDI r14 64
+    STR r15 r14
+    LDI r14 81
+    STR r15 r14 1
+    LDI r14 100
+    STR r15 r14 2
+    LDI r14 121
+    STR r15 r14 3
+    LDI r14 144
+    STR r15 r14 4
+    LDI r14 169
+    STR r15 r14 5
+    LDI r14 196
+    STR r15 r14 6
+    LDI r14 225
+    STR r15 r14 7
+    LDI r15 16
+    STR r15 r0
+    LDI r14 1
+    STR r15 r14 1
+    LDI r15 18
+    LDI r14 "H"
+    STR r15 r14
+    LDI r14 "E"
+    STR r15 r14 1
+    LDI r14 "L"
+    STR r15 r14 2
+    STR r15 r14 3
+    LDI r14 "O"
+    STR r15 r14 4
+    STR r15 r0 5
+    LDI r15 24
+    LDI r14 LINES
+    STR r15 r14
+    LDI r14 8
+    STR r15 r14 1
+    LDI r14 0
+    STR r15 r14 2
+    LDI r14 -1
+    STR r15 r14 3
+    LDI r14 'x'
+    STR r15 r14 4
+    LDI r1 0
+    LOD r1 r2 3
+    LDI r1 18
+    LOD r1 r3
+    HLT
@@ -0,0 +1,14 @@
+// Lookup tables in data memory, written once at startup
+define LINES 4
+
+.table squares 0, 1, 4, 9, 16, 25, 36, 49, 64, 81, 100, 121, 144, 169, 196, 225, 0, 1
+.table message "HELLO", 0
+.table layout LINES, LINES * 2, .main >> 1, -1, 'x'
+
+.main
+    .init_tables
+    LDI r1 squares
+    LOD r1 r2 3
+    LDI r1 message
+    LOD r1 r3
+    HLT
@@ -0,0 +1,13 @@
+// Hand-allocated version of vars.asm
+
+define score 0
+define board 1
+define lines 201
+
+.main
+    LDI r1, score
+    STR r1, r0
+    LDI r2, board
+    LDI r3, lines
+    LOD r3, r4
+    HLT
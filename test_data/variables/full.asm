@@ -0,0 +1,8 @@
+// The last array would run into the I/O ports at 240
+
+.array board 200
+.array queue 30
+.array history 20
+
+.main
+    HLT
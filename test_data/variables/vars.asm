@@ -0,0 +1,13 @@
+// Variables get data memory addresses from 0 upward, same binary as by_hand.asm
+
+.var score
+.array board 200
+.var lines
+
+.main
+    LDI r1, score
+    STR r1, r0
+    LDI r2, board
+    LDI r3, lines
+    LOD r3, r4
+    HLT
@@ -0,0 +1,23 @@
+// Hand-allocated version of virtual.asm
+
+.main
+    LDI r1, 10
+    LDI r2, 5
+    LDI r3, 0
+..again
+    ADD r3, r2, r3
+    DEC r2
+    BNE ..again
+    ADD r3, r1, r1
+    LDI r2, 3
+    CAL .double
+    ADD r1, r2, r1
+    HLT
+
+.double
+    LDI r3, 2
+..loop
+    ADD r1, r1, r1
+    DEC r3
+    BNE ..loop
+    RET
@@ -0,0 +1,21 @@
+// Only r15 is left, which is not enough to spill to
+
+.main
+    LDI r1, 1
+    LDI r2, 2
+    LDI r3, 3
+    LDI r4, 4
+    LDI r5, 5
+    LDI r6, 6
+    LDI r7, 7
+    LDI r8, 8
+    LDI r9, 9
+    LDI r10, 10
+    LDI r11, 11
+    LDI r12, 12
+    LDI r13, 13
+    LDI r14, 14
+    LDI %a, 1
+    LDI %b, 2
+    ADD %a, %b, r1
+    HLT
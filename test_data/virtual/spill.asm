@@ -0,0 +1,25 @@
+// Only r12 to r15 are left, so r14 and r15 are set aside to load the virtual registers
+// that do not fit from data memory, same binary as spill_by_hand.asm
+
+.main
+    LDI r1, 1
+    LDI r2, 2
+    LDI r3, 3
+    LDI r4, 4
+    LDI r5, 5
+    LDI r6, 6
+    LDI r7, 7
+    LDI r8, 8
+    LDI r9, 9
+    LDI r10, 10
+    LDI r11, 11
+    LDI %a, 12
+    LDI %b, 13
+    LDI %c, 14
+    LDI %d, 15
+    LDI %e, 16
+    ADD %a, %b, %a
+    ADD %c, %d, %c
+    ADD %a, %c, r1
+    ADD %e, r1, r1
+    HLT
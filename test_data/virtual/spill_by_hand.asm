@@ -0,0 +1,44 @@
+// Hand-allocated version of spill.asm
+
+.var saved_c
+.var saved_d
+.var saved_e
+
+.main
+    LDI r1, 1
+    LDI r2, 2
+    LDI r3, 3
+    LDI r4, 4
+    LDI r5, 5
+    LDI r6, 6
+    LDI r7, 7
+    LDI r8, 8
+    LDI r9, 9
+    LDI r10, 10
+    LDI r11, 11
+    LDI r12, 12
+    LDI r13, 13
+    LDI r14, 14
+    LDI r15, saved_c
+    STR r15, r14
+    LDI r14, 15
+    LDI r15, saved_d
+    STR r15, r14
+    LDI r14, 16
+    LDI r15, saved_e
+    STR r15, r14
+    ADD r12, r13, r12
+    LDI r14, saved_c
+    LOD r14, r14
+    LDI r15, saved_d
+    LOD r15, r15
+    ADD r14, r15, r14
+    LDI r15, saved_c
+    STR r15, r14
+    LDI r14, saved_c
+    LOD r14, r14
+    ADD r12, r14, r1
+    LDI r14, saved_e
+    LOD r14, r14
+    ADD r14, r1, r1
+    HLT
@@ -0,0 +1,24 @@
+// Virtual registers get the lowest registers the program does not name itself,
+// same binary as by_hand.asm
+
+.main
+    LDI r1, 10
+    LDI %count, 5
+    LDI %sum, 0
+..again
+    ADD %sum, %count, %sum
+    DEC %count
+    BNE ..again
+    ADD %sum, r1, r1
+    LDI %n, 3
+    CAL .double
+    ADD r1, %n, r1
+    HLT
+
+// %n is live across the call, so %x cannot have its register, but it can reuse %sum's
+.double
+    LDI %x, 2
+.loop %x
+    ADD r1, r1, r1
+.endloop
+    RET